@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE subscription_tokens
+        ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+        ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '1 day';
+    ALTER TABLE subscription_tokens ALTER COLUMN created_at DROP DEFAULT;
+    ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
+COMMIT;
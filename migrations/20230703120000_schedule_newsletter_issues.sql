@@ -0,0 +1,11 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE newsletter_issues
+        ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz,
+        ALTER COLUMN published_at DROP NOT NULL,
+        ADD COLUMN scheduled_for timestamptz NULL,
+        ADD COLUMN scheduled_timezone TEXT NULL,
+        ADD COLUMN status TEXT NULL;
+    UPDATE newsletter_issues SET status = 'published';
+    ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+COMMIT;
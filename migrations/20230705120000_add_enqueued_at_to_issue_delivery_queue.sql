@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN enqueued_at timestamptz NOT NULL DEFAULT now();
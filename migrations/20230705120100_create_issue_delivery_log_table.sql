@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_log (
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    -- One of 'sent', 'failed', 'skipped_invalid_email' or 'skipped_unsubscribed'
+    outcome TEXT NOT NULL,
+    message_id TEXT NULL,
+    error TEXT NULL,
+    n_attempts SMALLINT NOT NULL,
+    enqueued_at timestamptz NOT NULL,
+    completed_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+    -- Issues published so far get the same kind of slug as new ones.
+    UPDATE newsletter_issues
+    SET slug = concat_ws(
+        '-',
+        NULLIF(trim(both '-' from regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+        left(replace(newsletter_issue_id::text, '-', ''), 8)
+    )
+    WHERE status = 'published';
+COMMIT;
@@ -0,0 +1,10 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE users ADD COLUMN role TEXT NULL;
+    -- Whoever could log in so far could do everything.
+    UPDATE users SET role = 'owner';
+    ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+    ALTER TABLE users ADD CONSTRAINT users_role_check
+        CHECK (role IN ('owner', 'editor', 'viewer'));
+    ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
+COMMIT;
@@ -0,0 +1,15 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+    -- Sessions created before this instant are no longer valid.
+    ALTER TABLE users ADD COLUMN sessions_revoked_at timestamptz NULL;
+    CREATE TABLE password_reset_tokens(
+        -- Only a SHA-256 digest is stored: a leaked table can't be used
+        -- to take over accounts.
+        token_hash TEXT NOT NULL PRIMARY KEY,
+        user_id uuid NOT NULL REFERENCES users (user_id),
+        created_at timestamptz NOT NULL,
+        expires_at timestamptz NOT NULL,
+        used_at timestamptz NULL
+    );
+COMMIT;
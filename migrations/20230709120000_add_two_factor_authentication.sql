@@ -0,0 +1,22 @@
+-- Add migration script here
+BEGIN;
+    -- Base32-encoded, as in the provisioning URI.
+    ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+    -- NULL while the user has not proved that their authenticator works.
+    ALTER TABLE users ADD COLUMN totp_confirmed_at timestamptz NULL;
+    -- A code can't be replayed once it has been used to log in.
+    ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+    CREATE TABLE totp_recovery_codes(
+        recovery_code_id uuid NOT NULL PRIMARY KEY,
+        user_id uuid NOT NULL REFERENCES users (user_id),
+        -- Hashed with argon2, like passwords.
+        code_hash TEXT NOT NULL,
+        used_at timestamptz NULL
+    );
+    CREATE TABLE security_settings(
+        -- There is a single row of settings.
+        id BOOLEAN NOT NULL PRIMARY KEY DEFAULT true CHECK (id),
+        require_two_factor BOOLEAN NOT NULL
+    );
+    INSERT INTO security_settings (require_two_factor) VALUES (false);
+COMMIT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+BEGIN;
+    -- One row per logged-in session: deleting it revokes the session.
+    CREATE TABLE user_sessions(
+        session_id uuid NOT NULL PRIMARY KEY,
+        user_id uuid NOT NULL REFERENCES users (user_id),
+        created_at timestamptz NOT NULL,
+        last_seen_at timestamptz NOT NULL,
+        ip_address TEXT NOT NULL,
+        user_agent TEXT NOT NULL
+    );
+    CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+COMMIT;
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE api_tokens(
+    token_id uuid NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    name TEXT NOT NULL,
+    -- Like password reset tokens, only a SHA-256 digest is stored.
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
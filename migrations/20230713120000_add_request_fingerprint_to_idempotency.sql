@@ -0,0 +1,3 @@
+-- Add migration script here
+-- NULL for the keys saved before fingerprints existed: they are not checked.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- The slugs backfilled for issues published before slugs existed kept the
+-- whole title: cap it at 60 characters, as `IssueSlug` does for new issues.
+UPDATE newsletter_issues
+SET slug = concat_ws(
+    '-',
+    NULLIF(
+        rtrim(left(ltrim(regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g'), '-'), 60), '-'),
+        ''
+    ),
+    left(replace(newsletter_issue_id::text, '-', ''), 8)
+)
+WHERE length(slug) > 60 + 1 + 8;
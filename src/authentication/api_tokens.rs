@@ -0,0 +1,241 @@
+use crate::authentication::Role;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Tokens are easy to spot in logs and secret scanners thanks to their prefix.
+const TOKEN_PREFIX: &str = "zp_";
+
+/// What an API token can be used for, on top of what its owner's role allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiScope {
+    PublishNewsletters,
+    ReadIssues,
+    ReadSubscribers,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [
+        ApiScope::PublishNewsletters,
+        ApiScope::ReadIssues,
+        ApiScope::ReadSubscribers,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "newsletters:publish",
+            ApiScope::ReadIssues => "issues:read",
+            ApiScope::ReadSubscribers => "subscribers:read",
+        }
+    }
+
+    /// The least privileged role that can be granted the scope.
+    pub fn required_role(&self) -> Role {
+        match self {
+            ApiScope::PublishNewsletters => Role::Editor,
+            ApiScope::ReadIssues | ApiScope::ReadSubscribers => Role::Viewer,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid scope.", s))
+    }
+}
+
+/// The user behind an API request and what their token lets them do.
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiClient {
+    /// Both the token and its owner's current role must allow the scope:
+    /// demoting a user also restricts their existing tokens.
+    pub fn is_allowed(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope) && self.role >= scope.required_role()
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+    let mut rng = thread_rng();
+    let secret: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    format!("{TOKEN_PREFIX}{secret}")
+}
+
+/// Returns the token itself: it can't be recovered afterwards.
+#[tracing::instrument(name = "Create an API token", skip(pool))]
+pub async fn create_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok(Secret::new(token))
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if the user has no such active token.
+#[tracing::instrument(name = "Revoke an API token", skip(pool))]
+pub async fn revoke_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Looks up an active token of an active user, and records its use.
+#[tracing::instrument(name = "Authenticate an API token", skip(pool, token))]
+pub async fn authenticate_api_token(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<ApiClient>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens t
+        SET last_used_at = now()
+        FROM users u
+        WHERE
+            t.user_id = u.user_id AND
+            t.token_hash = $1 AND
+            t.revoked_at IS NULL AND
+            NOT u.disabled
+        RETURNING t.user_id, t.scopes, u.role
+        "#,
+        hash_token(token.expose_secret())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to authenticate the API token.")?;
+    row.map(|r| {
+        Ok(ApiClient {
+            user_id: r.user_id,
+            role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+            // Scopes that no longer exist are ignored.
+            scopes: r
+                .scopes
+                .into_iter()
+                .filter_map(|s| ApiScope::try_from(s).ok())
+                .collect(),
+        })
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token, ApiClient, ApiScope};
+    use crate::authentication::Role;
+    use claims::assert_err;
+    use uuid::Uuid;
+
+    #[test]
+    fn scopes_round_trip_through_their_name() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::try_from(scope.to_string()), Ok(scope));
+        }
+    }
+
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        assert_err!(ApiScope::try_from("users:write".to_string()));
+    }
+
+    #[test]
+    fn tokens_are_prefixed_and_unique() {
+        let (a, b) = (generate_token(), generate_token());
+        assert!(a.starts_with("zp_"));
+        assert_eq!(a.len(), 43);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn the_role_of_the_owner_caps_the_scopes() {
+        let client = |role| ApiClient {
+            user_id: Uuid::new_v4(),
+            role,
+            scopes: vec![ApiScope::PublishNewsletters, ApiScope::ReadIssues],
+        };
+        assert!(client(Role::Editor).is_allowed(ApiScope::PublishNewsletters));
+        assert!(!client(Role::Viewer).is_allowed(ApiScope::PublishNewsletters));
+        assert!(client(Role::Viewer).is_allowed(ApiScope::ReadIssues));
+        assert!(!client(Role::Owner).is_allowed(ApiScope::ReadSubscribers));
+    }
+}
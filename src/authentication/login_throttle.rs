@@ -0,0 +1,151 @@
+use crate::configuration::LoginThrottlingSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+/// Whose failed attempts are being counted, on top of the client IP.
+pub enum LoginAccount<'a> {
+    /// Attempts at the password step.
+    Username(&'a str),
+    /// Attempts at the two-factor step, once the password was right.
+    UserId(Uuid),
+    /// Password reset requests for an email address, each of which counts
+    /// whether or not an account uses it.
+    PasswordReset(&'a str),
+}
+
+impl LoginAccount<'_> {
+    fn key(&self) -> String {
+        match self {
+            LoginAccount::Username(username) => format!("username:{}", username),
+            LoginAccount::UserId(user_id) => format!("user_id:{}", user_id),
+            LoginAccount::PasswordReset(email) => format!("password_reset:{}", email),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ThrottleError {
+    #[error("Too many failed login attempts.")]
+    LockedOut,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// Slows down and then locks out whoever keeps failing to log in, see
+/// `LoginThrottlingSettings`. The counters live in Redis so that they are
+/// shared by all the instances of the application.
+pub struct LoginThrottle {
+    redis: ConnectionManager,
+    settings: LoginThrottlingSettings,
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottlingSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let redis = redis::Client::open(redis_uri.expose_secret().as_str())?
+            .get_tokio_connection_manager()
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(Self { redis, settings })
+    }
+
+    /// Fails right away if the account or the IP is locked out, otherwise
+    /// waits longer the more recent failures there have been.
+    #[tracing::instrument(name = "Wait for login turn", skip(self, account), fields(account = %account.key()))]
+    pub async fn wait_for_turn(
+        &self,
+        account: &LoginAccount<'_>,
+        ip: &str,
+    ) -> Result<(), ThrottleError> {
+        let mut redis = self.redis.clone();
+        let account_key = account.key();
+        let ip_key = format!("ip:{}", ip);
+        for key in [&account_key, &ip_key] {
+            let is_locked_out: bool = redis
+                .exists(lockout_key(key))
+                .await
+                .context("Failed to check for a login lockout")?;
+            if is_locked_out {
+                return Err(ThrottleError::LockedOut);
+            }
+        }
+        let n_failures: Option<u32> = redis
+            .get(failures_key(&account_key))
+            .await
+            .context("Failed to retrieve the number of failed logins")?;
+        let delay = self.settings.delay(n_failures.unwrap_or(0));
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Record failed login", skip(self, account), fields(account = %account.key()))]
+    pub async fn record_failure(
+        &self,
+        account: &LoginAccount<'_>,
+        ip: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.count_failure(&account.key(), self.settings.max_failures_per_username)
+            .await?;
+        self.count_failure(&format!("ip:{}", ip), self.settings.max_failures_per_ip)
+            .await
+    }
+
+    /// Forgets the failures of the account, but not those of the IP: an
+    /// attacker could otherwise reset their counter with their own account.
+    #[tracing::instrument(name = "Record successful login", skip(self, account), fields(account = %account.key()))]
+    pub async fn record_success(&self, account: &LoginAccount<'_>) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        redis
+            .del::<_, ()>(failures_key(&account.key()))
+            .await
+            .context("Failed to reset the number of failed logins")?;
+        Ok(())
+    }
+
+    async fn count_failure(&self, key: &str, max_failures: u32) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let failures_key = failures_key(key);
+        let n_failures: u32 = redis
+            .incr(&failures_key, 1)
+            .await
+            .context("Failed to count a failed login")?;
+        if n_failures == 1 {
+            redis
+                .expire::<_, ()>(&failures_key, self.settings.failure_window_seconds as usize)
+                .await
+                .context("Failed to set the failed logins window")?;
+        }
+        if n_failures >= max_failures {
+            redis
+                .set_ex::<_, _, ()>(lockout_key(key), 1, self.settings.lockout_seconds as usize)
+                .await
+                .context("Failed to lock out after too many failed logins")?;
+            redis
+                .del::<_, ()>(&failures_key)
+                .await
+                .context("Failed to reset the number of failed logins")?;
+            tracing::warn!(
+                locked_out = %key,
+                n_failures,
+                lockout_seconds = self.settings.lockout_seconds,
+                "Locked out after too many failed logins"
+            );
+        }
+        Ok(())
+    }
+}
+
+fn failures_key(key: &str) -> String {
+    format!("login_failures:{}", key)
+}
+
+fn lockout_key(key: &str) -> String {
+    format!("login_lockout:{}", key)
+}
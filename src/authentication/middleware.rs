@@ -1,9 +1,17 @@
-use crate::utils::{e500, see_other};
+use crate::authentication::api_tokens::authenticate_api_token;
+use crate::authentication::{touch_session, Role};
+use crate::utils::{client_ip, e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::HttpMessage;
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::http::Method;
 use actix_web::{error::InternalError, FromRequest};
+use actix_web::{web, HttpMessage, HttpResponse};
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
@@ -34,15 +42,196 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user_id = session.get_user_id().map_err(e500)?;
+    let session_id = session.get_session_id().map_err(e500)?;
+    let user = match (user_id, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .expect("The database pool is registered as application data");
+            let ip_address = client_ip(req.request());
+            let is_tracked = touch_session(pool, user_id, session_id, &ip_address)
+                .await
+                .context("Failed to update the session's last activity")
+                .map_err(e500)?;
+            if is_tracked {
+                let logged_in_at = session.get_logged_in_at().map_err(e500)?;
+                get_active_user(pool, user_id)
+                    .await
+                    .map_err(e500)?
+                    .filter(|user| match user.sessions_revoked_at {
+                        Some(revoked_at) => logged_in_at.is_some_and(|t| t > revoked_at),
+                        None => true,
+                    })
+                    .map(|user| (user_id, user.role, user.two_factor_missing))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    match user {
+        Some((user_id, role, two_factor_missing)) => {
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
+            if two_factor_missing {
+                req.extensions_mut().insert(TwoFactorSetupRequired);
+            }
             next.call(req).await
         }
         None => {
+            // The account may have been disabled, or the session revoked,
+            // since it was created.
+            session.log_out();
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in");
             Err(InternalError::from_response(e, response).into())
         }
     }
 }
+
+/// Authenticates API requests with an `Authorization: Bearer <token>` header,
+/// in place of the session cookie of the admin area.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|token| Secret::new(token.trim().to_owned()));
+    let client = match token {
+        Some(token) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .expect("The database pool is registered as application data");
+            authenticate_api_token(pool, &token).await.map_err(e500)?
+        }
+        None => None,
+    };
+    match client {
+        Some(client) => {
+            req.extensions_mut().insert(UserId(client.user_id));
+            req.extensions_mut().insert(client);
+            next.call(req).await
+        }
+        None => {
+            let response = HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="api""#))
+                .json(serde_json::json!({ "error": "A valid API token is required." }));
+            let e = anyhow::anyhow!("Missing or invalid API token");
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// Set on requests from users who must set up two-factor authentication.
+struct TwoFactorSetupRequired;
+
+/// When two-factor authentication is required, users who haven't set it up
+/// yet can only do that (or log out).
+/// Must be wrapped by `reject_anonymous_users`.
+pub async fn reject_users_without_two_factor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    const SETUP_ACTIONS: [&str; 4] = [
+        "/admin/two-factor",
+        "/admin/two-factor/setup",
+        "/admin/two-factor/confirm",
+        "/admin/logout",
+    ];
+    let setup_required = req.extensions().get::<TwoFactorSetupRequired>().is_some();
+    if setup_required && !SETUP_ACTIONS.contains(&req.path()) {
+        let response = see_other("/admin/two-factor");
+        let e = anyhow::anyhow!("The user must set up two-factor authentication first");
+        return Err(InternalError::from_response(e, response).into());
+    }
+    next.call(req).await
+}
+
+/// Viewers can look around the admin area, manage their own password,
+/// sessions, API tokens and two-factor authentication, and log out; any other
+/// non-GET request is forbidden.
+/// Must be wrapped by `reject_anonymous_users`.
+pub async fn reject_viewers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    const VIEWER_ACTIONS: [&str; 9] = [
+        "/admin/password",
+        "/admin/api-tokens",
+        "/admin/api-tokens/revoke",
+        "/admin/sessions/revoke",
+        "/admin/sessions/revoke-others",
+        "/admin/two-factor/setup",
+        "/admin/two-factor/confirm",
+        "/admin/two-factor/disable",
+        "/admin/logout",
+    ];
+    let is_read_only = req.method() == Method::GET || VIEWER_ACTIONS.contains(&req.path());
+    if is_read_only {
+        return next.call(req).await;
+    }
+    require_role(&req, Role::Editor)?;
+    next.call(req).await
+}
+
+/// Must be wrapped by `reject_anonymous_users`.
+pub async fn reject_non_owners(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(&req, Role::Owner)?;
+    next.call(req).await
+}
+
+fn require_role(req: &ServiceRequest, required: Role) -> Result<(), actix_web::Error> {
+    match req.extensions().get::<Role>() {
+        Some(role) if *role >= required => Ok(()),
+        _ => {
+            let e = anyhow::anyhow!("This action requires the {required} role");
+            Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into())
+        }
+    }
+}
+
+struct ActiveUser {
+    role: Role,
+    sessions_revoked_at: Option<DateTime<Utc>>,
+    two_factor_missing: bool,
+}
+
+#[tracing::instrument(name = "Get active user", skip(pool))]
+async fn get_active_user(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<ActiveUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            role,
+            sessions_revoked_at,
+            (
+                totp_confirmed_at IS NULL AND
+                (SELECT require_two_factor FROM security_settings)
+            ) as "two_factor_missing!"
+        FROM users
+        WHERE user_id = $1 AND NOT disabled
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the user's role.")?;
+    row.map(|r| {
+        Ok(ActiveUser {
+            role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+            sessions_revoked_at: r.sessions_revoked_at,
+            two_factor_missing: r.two_factor_missing,
+        })
+    })
+    .transpose()
+}
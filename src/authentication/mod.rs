@@ -1,5 +1,28 @@
+mod api_tokens;
+mod login_throttle;
 mod middleware;
 mod password;
+mod role;
+mod sessions;
+mod totp;
+mod two_factor;
 
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use api_tokens::{
+    create_api_token, list_api_tokens, revoke_api_token, ApiClient, ApiScope, ApiToken,
+};
+pub use login_throttle::{LoginAccount, LoginThrottle, ThrottleError};
+pub use middleware::{
+    reject_anonymous_users, reject_invalid_api_tokens, reject_non_owners,
+    reject_users_without_two_factor, reject_viewers, UserId,
+};
+pub use password::{change_password, create_user, validate_credentials, AuthError, Credentials};
+pub use role::Role;
+pub use sessions::{
+    list_sessions, record_session, revoke_all_sessions, revoke_other_sessions, revoke_session,
+    touch_session, ActiveSession, SESSION_TTL_HOURS,
+};
+pub use totp::TotpSecret;
+pub use two_factor::{
+    confirm_two_factor_setup, disable_two_factor, get_two_factor_status, is_two_factor_required,
+    set_two_factor_required, start_two_factor_setup, verify_second_factor, TwoFactorStatus,
+};
@@ -1,9 +1,11 @@
+use crate::authentication::Role;
+use crate::domain::SubscriberEmail;
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -45,11 +47,13 @@ pub async fn validate_credentials(
         .map_err(AuthError::InvalidCredentials)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
-pub async fn change_password(
+/// Takes a transaction when the change has to be committed together with
+/// other updates, e.g. using up a password reset token.
+#[tracing::instrument(name = "Change password", skip(password, executor))]
+pub async fn change_password<'e>(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    executor: impl PgExecutor<'e>,
 ) -> Result<(), anyhow::Error> {
     let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
         .await?
@@ -63,12 +67,43 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
     Ok(())
 }
 
+/// Returns `None` if the username or the email address is already taken.
+#[tracing::instrument(name = "Create user", skip(password, pool))]
+pub async fn create_user(
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    password: Secret<String>,
+    role: Role,
+    pool: &PgPool,
+) -> Result<Option<uuid::Uuid>, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password_hash, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        RETURNING user_id
+        "#,
+        uuid::Uuid::new_v4(),
+        username,
+        email.map(|e| e.as_ref()),
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to insert the new user in the database.")?;
+    Ok(row.map(|r| r.user_id))
+}
+
 async fn get_stored_credentials(
     username: &str,
     pool: &PgPool,
@@ -77,7 +112,7 @@ async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND NOT disabled
         "#,
         username,
     )
@@ -92,7 +127,7 @@ async fn get_stored_credentials(
     name = "Verify password hash",
     skip(expected_password_hash, password_candidate)
 )]
-fn verify_password_hash(
+pub(super) fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
 ) -> Result<(), AuthError> {
@@ -108,7 +143,9 @@ fn verify_password_hash(
         .map_err(AuthError::InvalidCredentials)
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub(super) fn compute_password_hash(
+    password: Secret<String>,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
     let password_hash = Argon2::new(
         Algorithm::Argon2id,
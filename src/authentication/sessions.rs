@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// How long a session stays valid after logging in. It is also the TTL of
+/// the session state in Redis, so that both expire together.
+pub const SESSION_TTL_HOURS: i64 = 24;
+
+/// An entry of the per-user session index, as shown on the dashboard.
+pub struct ActiveSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: String,
+    pub user_agent: String,
+}
+
+/// Adds a freshly logged-in session to the index, dropping the user's
+/// sessions that have expired in the meantime.
+#[tracing::instrument(name = "Record a new session", skip(pool, user_agent))]
+pub async fn record_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    ip_address: &str,
+    user_agent: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let session_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND created_at < now() - make_interval(hours => $2)
+        "#,
+        user_id,
+        SESSION_TTL_HOURS as i32
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (
+            session_id, user_id, created_at, last_seen_at, ip_address, user_agent
+        )
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        ip_address,
+        user_agent
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(session_id)
+}
+
+/// Bumps the last activity of a session.
+/// Returns `false` if the session has been revoked.
+#[tracing::instrument(name = "Touch a session", skip(pool))]
+pub async fn touch_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+    ip_address: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now(), ip_address = $3
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id,
+        ip_address
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "List active sessions", skip(pool))]
+pub async fn list_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ActiveSession>, sqlx::Error> {
+    sqlx::query_as!(
+        ActiveSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM user_sessions
+        WHERE user_id = $1 AND created_at >= now() - make_interval(hours => $2)
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        SESSION_TTL_HOURS as i32
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if the user has no such session.
+#[tracing::instrument(name = "Revoke a session", skip(pool))]
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM user_sessions WHERE session_id = $1 AND user_id = $2",
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Revoke all other sessions of a user", skip(executor))]
+pub async fn revoke_other_sessions<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM user_sessions WHERE user_id = $1 AND session_id <> $2",
+        user_id,
+        current_session_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Logs the user out everywhere: `reject_anonymous_users` turns away any
+/// session that was opened before now.
+#[tracing::instrument(name = "Revoke all sessions of a user", skip(transaction))]
+pub async fn revoke_all_sessions(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET sessions_revoked_at = $2 WHERE user_id = $1",
+        user_id,
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!("DELETE FROM user_sessions WHERE user_id = $1", user_id)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+
+const DIGITS: u32 = 6;
+const STEP_SECONDS: i64 = 30;
+/// How many steps a code may be off by, to make up for clock drift.
+const ALLOWED_SKEW: i64 = 1;
+const ISSUER: &str = "zero2prod";
+
+/// The shared secret of an RFC 6238 (TOTP) authenticator, with the defaults
+/// every authenticator app supports: HMAC-SHA1, 6 digits, 30 second steps.
+pub struct TotpSecret(Secret<Vec<u8>>);
+
+impl TotpSecret {
+    pub fn generate() -> Self {
+        let mut key = vec![0; 20];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(Secret::new(key))
+    }
+
+    pub fn parse(base32: &str) -> Result<Self, String> {
+        BASE32_NOPAD
+            .decode(base32.as_bytes())
+            .ok()
+            .filter(|key| !key.is_empty())
+            .map(|key| Self(Secret::new(key)))
+            .ok_or_else(|| "The TOTP secret is not valid base32.".to_string())
+    }
+
+    /// The form in which the secret is stored and typed into apps by hand.
+    pub fn to_base32(&self) -> String {
+        BASE32_NOPAD.encode(self.0.expose_secret())
+    }
+
+    /// The `otpauth://` URI that authenticator apps read from a QR code.
+    pub fn provisioning_uri(&self, account_name: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&digits={}&period={}",
+            ISSUER,
+            percent_encode(account_name),
+            self.to_base32(),
+            ISSUER,
+            DIGITS,
+            STEP_SECONDS
+        )
+    }
+
+    /// Returns the time step the code belongs to if it is valid at `unix_time`.
+    pub fn verify(&self, code: &str, unix_time: i64) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let current_step = unix_time / STEP_SECONDS;
+        (current_step - ALLOWED_SKEW..=current_step + ALLOWED_SKEW)
+            .find(|step| self.code_at_step(*step) == code)
+    }
+
+    /// The code an authenticator app shows at `unix_time`.
+    pub fn code_at(&self, unix_time: i64) -> String {
+        self.code_at_step(unix_time / STEP_SECONDS)
+    }
+
+    fn code_at_step(&self, step: i64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(self.0.expose_secret())
+            .expect("HMAC can take a key of any size");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+        // Dynamic truncation, see RFC 4226 section 5.3.
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        let binary = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(DIGITS),
+            width = DIGITS as usize
+        )
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TotpSecret;
+    use claims::{assert_none, assert_some_eq};
+    use secrecy::Secret;
+
+    /// The SHA-1 key of the RFC 6238 test vectors.
+    fn rfc_secret() -> TotpSecret {
+        TotpSecret(Secret::new(b"12345678901234567890".to_vec()))
+    }
+
+    #[test]
+    fn codes_match_the_rfc_test_vectors() {
+        // The RFC lists 8-digit codes, we only keep the last 6 digits.
+        for (unix_time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            assert_some_eq!(rfc_secret().verify(code, unix_time), unix_time / 30);
+        }
+    }
+
+    #[test]
+    fn codes_from_the_adjacent_steps_are_accepted() {
+        assert_some_eq!(
+            rfc_secret().verify("081804", 1111111109 + 30),
+            1111111109 / 30
+        );
+        assert_none!(rfc_secret().verify("081804", 1111111109 + 60));
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        assert_none!(rfc_secret().verify("28708", 59));
+        assert_none!(rfc_secret().verify("28708a", 59));
+    }
+
+    #[test]
+    fn secrets_round_trip_through_base32() {
+        let secret = TotpSecret::generate();
+        let parsed = TotpSecret::parse(&secret.to_base32()).unwrap();
+        assert_eq!(parsed.to_base32(), secret.to_base32());
+        assert!(TotpSecret::parse("not base32!").is_err());
+    }
+
+    #[test]
+    fn the_provisioning_uri_names_the_issuer_and_account() {
+        let uri = rfc_secret().provisioning_uri("ursula le guin");
+        assert_eq!(
+            uri,
+            "otpauth://totp/zero2prod:ursula%20le%20guin?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=zero2prod&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use super::password::{compute_password_hash, verify_password_hash};
+use super::TotpSecret;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const N_RECOVERY_CODES: usize = 10;
+
+pub enum TwoFactorStatus {
+    Disabled,
+    /// A secret has been generated, but the user has not entered a code yet.
+    Pending(TotpSecret),
+    Enabled,
+}
+
+#[tracing::instrument(name = "Get two-factor status", skip(pool))]
+pub async fn get_two_factor_status(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<TwoFactorStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT totp_secret, totp_confirmed_at FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the two-factor status.")?;
+    let status = match (row.totp_secret, row.totp_confirmed_at) {
+        (Some(_), Some(_)) => TwoFactorStatus::Enabled,
+        (Some(secret), None) => {
+            TwoFactorStatus::Pending(TotpSecret::parse(&secret).map_err(anyhow::Error::msg)?)
+        }
+        (None, _) => TwoFactorStatus::Disabled,
+    };
+    Ok(status)
+}
+
+/// Replaces any pending secret with a new one; does nothing if two-factor
+/// authentication is already enabled.
+#[tracing::instrument(name = "Start two-factor setup", skip(pool))]
+pub async fn start_two_factor_setup(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2
+        WHERE user_id = $1 AND totp_confirmed_at IS NULL
+        "#,
+        user_id,
+        TotpSecret::generate().to_base32()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    Ok(())
+}
+
+/// Enables two-factor authentication if `code` matches the pending secret,
+/// returning the recovery codes to show (once) to the user.
+#[tracing::instrument(name = "Confirm two-factor setup", skip(pool, code))]
+pub async fn confirm_two_factor_setup(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let secret = match get_two_factor_status(pool, user_id).await? {
+        TwoFactorStatus::Pending(secret) => secret,
+        TwoFactorStatus::Disabled | TwoFactorStatus::Enabled => return Ok(None),
+    };
+    let step = match secret.verify(code, Utc::now().timestamp()) {
+        Some(step) => step,
+        None => return Ok(None),
+    };
+    let recovery_codes: Vec<String> = (0..N_RECOVERY_CODES)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let code_hashes = {
+        let recovery_codes = recovery_codes.clone();
+        spawn_blocking_with_tracing(move || {
+            recovery_codes
+                .into_iter()
+                .map(|c| compute_password_hash(Secret::new(normalize_recovery_code(&c))))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await?
+        .context("Failed to hash the recovery codes.")?
+    };
+    let mut transaction = pool.begin().await?;
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_confirmed_at = now(), totp_last_used_step = $2
+        WHERE user_id = $1 AND totp_confirmed_at IS NULL
+        "#,
+        user_id,
+        step
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enable two-factor authentication.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(None);
+    }
+    for code_hash in code_hashes {
+        sqlx::query!(
+            r#"
+            INSERT INTO totp_recovery_codes (recovery_code_id, user_id, code_hash)
+            VALUES ($1, $2, $3)
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            code_hash.expose_secret()
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to store a recovery code.")?;
+    }
+    transaction.commit().await?;
+    Ok(Some(recovery_codes))
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_confirmed_at = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to disable two-factor authentication.")?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the recovery codes.")?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Accepts either a code from the authenticator app or an unused recovery
+/// code. Either can only be used once.
+#[tracing::instrument(name = "Verify second factor", skip(pool, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret as "totp_secret!"
+        FROM users
+        WHERE user_id = $1 AND totp_confirmed_at IS NOT NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret.")?;
+    let secret = match row {
+        Some(row) => TotpSecret::parse(&row.totp_secret).map_err(anyhow::Error::msg)?,
+        None => return Ok(false),
+    };
+    if let Some(step) = secret.verify(code, Utc::now().timestamp()) {
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $2
+            WHERE
+                user_id = $1 AND
+                (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the use of a TOTP code.")?
+        .rows_affected();
+        return Ok(n_updated_rows > 0);
+    }
+    use_recovery_code(pool, user_id, code).await
+}
+
+#[tracing::instrument(name = "Use a recovery code", skip(pool, code))]
+async fn use_recovery_code(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let candidate = normalize_recovery_code(code);
+    if candidate.is_empty() {
+        return Ok(false);
+    }
+    let rows = sqlx::query!(
+        r#"
+        SELECT recovery_code_id, code_hash
+        FROM totp_recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the recovery codes.")?;
+    let stored_codes: Vec<_> = rows
+        .into_iter()
+        .map(|r| (r.recovery_code_id, Secret::new(r.code_hash)))
+        .collect();
+    let matching_code_id = spawn_blocking_with_tracing(move || {
+        stored_codes.into_iter().find_map(|(id, hash)| {
+            verify_password_hash(hash, Secret::new(candidate.clone()))
+                .is_ok()
+                .then_some(id)
+        })
+    })
+    .await?;
+    let recovery_code_id = match matching_code_id {
+        Some(id) => id,
+        None => return Ok(false),
+    };
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = now()
+        WHERE recovery_code_id = $1 AND used_at IS NULL
+        "#,
+        recovery_code_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to mark the recovery code as used.")?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+#[tracing::instrument(name = "Get the two-factor requirement", skip(pool))]
+pub async fn is_two_factor_required(pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!("SELECT require_two_factor FROM security_settings")
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the security settings.")?;
+    Ok(row.require_two_factor)
+}
+
+#[tracing::instrument(name = "Set the two-factor requirement", skip(pool))]
+pub async fn set_two_factor_required(pool: &PgPool, required: bool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE security_settings SET require_two_factor = $1",
+        required
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the security settings.")?;
+    Ok(())
+}
+
+/// Recovery codes look like `k3x9a-p2m7q`, but are checked without the dash
+/// and regardless of case.
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
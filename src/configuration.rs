@@ -1,26 +1,180 @@
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{
+    EmailSender, FileEmailClient, PostmarkClient, RateLimitedEmailSender, SmtpClient,
+};
 use config;
 use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
 
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
+    pub idempotency: IdempotencySettings,
     pub redis_uri: Secret<String>,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_retries: i16,
+    pub base_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+    /// How often an idle worker checks the queue while it can't listen for
+    /// enqueued tasks, e.g. after losing its connection to the database.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+    /// How many emails are sent concurrently, by as many workers.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub n_workers: usize,
+}
+
+impl IssueDeliverySettings {
+    /// Delay before the next attempt of a delivery that already failed
+    /// `n_retries + 1` times: it doubles at every retry, up to a ceiling.
+    pub fn backoff(&self, n_retries: i16) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(n_retries.max(0) as u32);
+        let backoff = self.base_backoff_milliseconds.saturating_mul(factor);
+        std::time::Duration::from_millis(backoff.min(self.max_backoff_milliseconds))
+    }
+
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct IdempotencySettings {
+    /// Past this age, a key can be reused as if it had never been seen.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    /// How long a request waits for another one with the same key to
+    /// complete before getting a 409 Conflict. 0 answers with a 409 at once.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrent_request_wait_milliseconds: u64,
+    /// The `Retry-After` of that 409 Conflict.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retry_after_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_interval_seconds: u64,
+    /// How many expired keys are deleted per query.
+    #[serde(deserialize_with = "deserialize_batch_size")]
+    pub cleanup_batch_size: i64,
+}
+
+impl IdempotencySettings {
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn concurrent_request_wait(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.concurrent_request_wait_milliseconds)
+    }
+
+    pub fn retry_after(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retry_after_seconds)
+    }
+
+    pub fn cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub provider: EmailProvider,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     timeout_milliseconds: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub outbox_directory: Option<String>,
+    pub rate_limit: RateLimitSettings,
+}
+
+/// Providers and the mail servers of the big mailbox providers throttle
+/// senders that go too fast.
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub messages_per_second: f64,
+    /// Limits for the recipients of some domains, e.g. `gmail.com`.
+    #[serde(default, deserialize_with = "deserialize_domain_rates")]
+    pub domains: HashMap<String, f64>,
+}
+
+/// A batch size of zero or less would never delete anything.
+fn deserialize_batch_size<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let batch_size: i64 = deserialize_number_from_string(deserializer)?;
+    if batch_size > 0 {
+        Ok(batch_size)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "{} is not a valid batch size: it must be positive.",
+            batch_size
+        )))
+    }
+}
+
+/// A rate of zero or less would never let an email through.
+fn validate_rate(rate: f64) -> Result<f64, String> {
+    if rate.is_finite() && rate > 0.0 {
+        Ok(rate)
+    } else {
+        Err(format!(
+            "{} is not a valid rate limit: it must be a positive number of messages per second.",
+            rate
+        ))
+    }
+}
+
+fn deserialize_rate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    validate_rate(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_domain_rates<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rates = HashMap::<String, f64>::deserialize(deserializer)?;
+    for (domain, &rate) in &rates {
+        validate_rate(rate).map_err(|e| serde::de::Error::custom(format!("{} ({})", e, domain)))?;
+    }
+    Ok(rates)
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    #[default]
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub require_tls: bool,
 }
 
 impl EmailClientSettings {
@@ -30,15 +184,51 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
-    pub fn client(self) -> EmailClient {
+    /// The client for the emails sent while someone waits for the response,
+    /// e.g. confirmation or password reset links: they are never held back.
+    pub fn client(self) -> Arc<dyn EmailSender> {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        let client: Arc<dyn EmailSender> = match self.provider {
+            EmailProvider::Postmark => Arc::new(PostmarkClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailProvider::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("The smtp email provider requires the smtp settings.");
+                Arc::new(
+                    SmtpClient::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.username.zip(smtp.password),
+                        smtp.require_tls,
+                        sender_email,
+                        timeout,
+                    )
+                    .expect("Failed to build the SMTP email client."),
+                )
+            }
+            EmailProvider::File => {
+                let directory = self
+                    .outbox_directory
+                    .expect("The file email provider requires an outbox directory.");
+                Arc::new(
+                    FileEmailClient::new(directory.into(), sender_email)
+                        .expect("Failed to build the file email client."),
+                )
+            }
+        };
+        client
+    }
+
+    /// The client of the delivery workers, throttled by `rate_limit`.
+    pub fn rate_limited_client(self) -> Arc<dyn EmailSender> {
+        let rate_limit = self.rate_limit.clone();
+        Arc::new(RateLimitedEmailSender::new(self.client(), &rate_limit))
     }
 }
 
@@ -49,6 +239,51 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub login_throttling: LoginThrottlingSettings,
+    /// How long in-flight requests and background tasks get to complete on
+    /// SIGTERM or SIGINT, before the process exits anyway.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
+    /// The reverse proxies in front of the application: the client IP is only
+    /// read from `X-Forwarded-For` on requests coming from one of them.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+/// Failed logins are counted per username and per client IP over a sliding
+/// window: each failure slows the next attempt down a bit more, and going over
+/// the limit locks that username (or IP) out for a while.
+#[derive(Deserialize, Clone)]
+pub struct LoginThrottlingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    base_delay_milliseconds: u64,
+    max_delay_milliseconds: u64,
+}
+
+impl LoginThrottlingSettings {
+    /// Delay before checking the credentials after `n_failures` recent failures:
+    /// none at first, then doubling at every failure, up to a ceiling.
+    pub fn delay(&self, n_failures: u32) -> std::time::Duration {
+        if n_failures == 0 {
+            return std::time::Duration::ZERO;
+        }
+        let factor = 2u64.saturating_pow(n_failures - 1);
+        let delay = self.base_delay_milliseconds.saturating_mul(factor);
+        std::time::Duration::from_millis(delay.min(self.max_delay_milliseconds))
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -135,3 +370,99 @@ impl DatabaseSettings {
             .ssl_mode(ssl_mode)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        IdempotencySettings, IssueDeliverySettings, LoginThrottlingSettings, RateLimitSettings,
+    };
+    use std::time::Duration;
+
+    fn settings() -> IssueDeliverySettings {
+        IssueDeliverySettings {
+            max_retries: 5,
+            base_backoff_milliseconds: 1000,
+            max_backoff_milliseconds: 5000,
+            poll_interval_milliseconds: 10000,
+            n_workers: 1,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_at_every_retry() {
+        assert_eq!(Duration::from_secs(1), settings().backoff(0));
+        assert_eq!(Duration::from_secs(2), settings().backoff(1));
+        assert_eq!(Duration::from_secs(4), settings().backoff(2));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(Duration::from_secs(5), settings().backoff(3));
+        assert_eq!(Duration::from_secs(5), settings().backoff(i16::MAX));
+    }
+
+    fn login_throttling_settings() -> LoginThrottlingSettings {
+        LoginThrottlingSettings {
+            max_failures_per_username: 5,
+            max_failures_per_ip: 20,
+            failure_window_seconds: 900,
+            lockout_seconds: 900,
+            base_delay_milliseconds: 250,
+            max_delay_milliseconds: 1000,
+        }
+    }
+
+    #[test]
+    fn login_delay_starts_after_the_first_failure_and_doubles() {
+        let settings = login_throttling_settings();
+        assert_eq!(Duration::ZERO, settings.delay(0));
+        assert_eq!(Duration::from_millis(250), settings.delay(1));
+        assert_eq!(Duration::from_millis(500), settings.delay(2));
+    }
+
+    #[test]
+    fn login_delay_is_capped() {
+        let settings = login_throttling_settings();
+        assert_eq!(Duration::from_secs(1), settings.delay(3));
+        assert_eq!(Duration::from_secs(1), settings.delay(u32::MAX));
+    }
+
+    fn rate_limit_settings(yaml: &str) -> Result<RateLimitSettings, config::ConfigError> {
+        config::Config::builder()
+            .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .build()?
+            .try_deserialize()
+    }
+
+    #[test]
+    fn positive_rate_limits_are_accepted() {
+        assert!(rate_limit_settings("messages_per_second: 0.5\ndomains:\n  gmail.com: 20").is_ok());
+    }
+
+    #[test]
+    fn rate_limits_of_zero_or_less_are_rejected() {
+        assert!(rate_limit_settings("messages_per_second: 0").is_err());
+        assert!(rate_limit_settings("messages_per_second: -1").is_err());
+        assert!(rate_limit_settings("messages_per_second: 50\ndomains:\n  gmail.com: 0").is_err());
+    }
+
+    fn idempotency_settings(
+        cleanup_batch_size: i64,
+    ) -> Result<IdempotencySettings, config::ConfigError> {
+        config::Config::builder()
+            .set_default("ttl_seconds", 86400)?
+            .set_default("concurrent_request_wait_milliseconds", 5000)?
+            .set_default("retry_after_seconds", 5)?
+            .set_default("cleanup_interval_seconds", 3600)?
+            .set_default("cleanup_batch_size", cleanup_batch_size)?
+            .build()?
+            .try_deserialize()
+    }
+
+    #[test]
+    fn cleanup_batch_sizes_of_zero_or_less_are_rejected() {
+        assert!(idempotency_settings(1000).is_ok());
+        assert!(idempotency_settings(0).is_err());
+        assert!(idempotency_settings(-1).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use uuid::Uuid;
+
+const MAX_TITLE_LENGTH: usize = 60;
+
+/// The URL-friendly name of a published issue in the public archive,
+/// e.g. `hello-world-1b4e28ba`.
+#[derive(Debug)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    /// The slug is derived from the title, with the start of the issue id
+    /// appended so that issues sharing a title don't collide.
+    pub fn new(title: &str, newsletter_issue_id: Uuid) -> Self {
+        let mut slug = String::new();
+        for c in title.chars().flat_map(char::to_lowercase) {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+            if slug.len() >= MAX_TITLE_LENGTH {
+                break;
+            }
+        }
+        let mut slug = slug.trim_end_matches('-').to_owned();
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&newsletter_issue_id.simple().to_string()[..8]);
+        Self(slug)
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueSlug;
+    use uuid::Uuid;
+
+    fn issue_id() -> Uuid {
+        Uuid::parse_str("1b4e28ba-2fa1-11d2-883f-0016d3cca427").unwrap()
+    }
+
+    #[test]
+    fn the_title_is_lowercased_and_hyphenated() {
+        let slug = IssueSlug::new("  Hello, World! Issue #3 ", issue_id());
+        assert_eq!(slug.as_ref(), "hello-world-issue-3-1b4e28ba");
+    }
+
+    #[test]
+    fn non_ascii_characters_are_dropped() {
+        let slug = IssueSlug::new("Ça va? 🎉", issue_id());
+        assert_eq!(slug.as_ref(), "a-va-1b4e28ba");
+    }
+
+    #[test]
+    fn long_titles_are_truncated() {
+        let slug = IssueSlug::new(&"a".repeat(200), issue_id());
+        assert_eq!(slug.as_ref(), format!("{}-1b4e28ba", "a".repeat(60)));
+    }
+
+    #[test]
+    fn titles_without_usable_characters_fall_back_on_the_id() {
+        let slug = IssueSlug::new("!!!", issue_id());
+        assert_eq!(slug.as_ref(), "1b4e28ba");
+    }
+}
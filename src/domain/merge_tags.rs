@@ -0,0 +1,170 @@
+use html_escape::encode_quoted_attribute;
+
+/// The merge tags that can appear in an issue, e.g. `Hi {{ name }}!`.
+const KNOWN_TAGS: [&str; 4] = ["name", "email", "unsubscribe_url", "subscribed_at"];
+
+/// The per-subscriber values substituted for merge tags at send time.
+#[derive(Debug)]
+pub struct MergeTags {
+    pub name: String,
+    pub email: String,
+    pub unsubscribe_url: String,
+    pub subscribed_at: String,
+}
+
+impl MergeTags {
+    /// Stand-in values for pages that have no recipient, e.g. the public archive.
+    pub fn anonymous(home_url: &str) -> Self {
+        Self {
+            name: "reader".into(),
+            email: String::new(),
+            unsubscribe_url: home_url.into(),
+            subscribed_at: String::new(),
+        }
+    }
+
+    /// Sample values for test sends, addressed to whoever asked for the test.
+    pub fn sample(email: &str, home_url: &str) -> Self {
+        Self {
+            name: "reader".into(),
+            email: email.into(),
+            unsubscribe_url: home_url.into(),
+            subscribed_at: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// Checks that every `{{ tag }}` in the template is one we know how to fill in.
+    pub fn validate(template: &str) -> Result<(), String> {
+        let mut unknown_tags = Vec::new();
+        for_each_tag(template, |tag| {
+            if !KNOWN_TAGS.contains(&tag) {
+                unknown_tags.push(tag.to_owned());
+            }
+        });
+        if unknown_tags.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unknown merge tags: {}. The available ones are: {}.",
+                unknown_tags.join(", "),
+                KNOWN_TAGS.join(", ")
+            ))
+        }
+    }
+
+    pub fn render_text(&self, template: &str) -> String {
+        self.render(template, |value| value.to_owned())
+    }
+
+    /// Values are escaped so that they are safe both in element content and
+    /// in attributes (e.g. `href="{{ unsubscribe_url }}"`).
+    pub fn render_html(&self, template: &str) -> String {
+        self.render(template, |value| {
+            encode_quoted_attribute(value).into_owned()
+        })
+    }
+
+    fn value(&self, tag: &str) -> Option<&str> {
+        match tag {
+            "name" => Some(&self.name),
+            "email" => Some(&self.email),
+            "unsubscribe_url" => Some(&self.unsubscribe_url),
+            "subscribed_at" => Some(&self.subscribed_at),
+            _ => None,
+        }
+    }
+
+    fn render(&self, template: &str, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some((before, tag, after)) = next_tag(rest) {
+            rendered.push_str(before);
+            match self.value(tag) {
+                Some(value) => rendered.push_str(&escape(value)),
+                // Unknown tags are rejected at publishing time, but issues
+                // published before merge tags existed are sent as they are.
+                None => rendered.push_str(&rest[before.len()..rest.len() - after.len()]),
+            }
+            rest = after;
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+fn for_each_tag<'a>(template: &'a str, mut f: impl FnMut(&'a str)) {
+    let mut rest = template;
+    while let Some((_, tag, after)) = next_tag(rest) {
+        f(tag);
+        rest = after;
+    }
+}
+
+/// Splits the template around its first `{{ tag }}`, returning the text
+/// before it, the trimmed tag name and the text after it.
+fn next_tag(template: &str) -> Option<(&str, &str, &str)> {
+    let start = template.find("{{")?;
+    let end = start + template[start..].find("}}")?;
+    Some((
+        &template[..start],
+        template[start + 2..end].trim(),
+        &template[end + 2..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MergeTags;
+    use claims::{assert_err, assert_ok};
+
+    fn merge_tags() -> MergeTags {
+        MergeTags {
+            name: "Ursula <Le Guin>".into(),
+            email: "ursula@example.com".into(),
+            unsubscribe_url: "https://example.com/unsubscribe?a=1&b=2".into(),
+            subscribed_at: "2023-07-01".into(),
+        }
+    }
+
+    #[test]
+    fn known_tags_are_valid() {
+        assert_ok!(MergeTags::validate(
+            "{{ name }} {{email}} {{ unsubscribe_url }} {{  subscribed_at }}"
+        ));
+    }
+
+    #[test]
+    fn unknown_tags_are_rejected() {
+        assert_err!(MergeTags::validate("Hi {{ first_name }}"));
+    }
+
+    #[test]
+    fn templates_without_tags_are_valid() {
+        assert_ok!(MergeTags::validate("Hi there, { not a tag }"));
+    }
+
+    #[test]
+    fn tags_are_replaced_as_is_in_text() {
+        assert_eq!(
+            merge_tags().render_text("Hi {{ name }} ({{email}}), since {{ subscribed_at }}"),
+            "Hi Ursula <Le Guin> (ursula@example.com), since 2023-07-01"
+        );
+    }
+
+    #[test]
+    fn tags_are_escaped_in_html() {
+        assert_eq!(
+            merge_tags()
+                .render_html(r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">Leave</a>"#),
+            r#"<p>Hi Ursula &lt;Le Guin&gt;</p><a href="https://example.com/unsubscribe?a=1&amp;b=2">Leave</a>"#
+        );
+    }
+
+    #[test]
+    fn unknown_tags_are_left_untouched() {
+        assert_eq!(
+            merge_tags().render_text("{{ first_name }} {{ name }}"),
+            "{{ first_name }} Ursula <Le Guin>"
+        );
+    }
+}
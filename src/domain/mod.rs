@@ -1,7 +1,17 @@
+mod issue_slug;
+mod merge_tags;
 mod new_subscriber;
+mod newsletter_content;
+mod schedule;
 mod subscriber_email;
 mod subscriber_name;
+mod unsubscribe_token;
 
+pub use issue_slug::IssueSlug;
+pub use merge_tags::MergeTags;
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_content::NewsletterContent;
+pub use schedule::Schedule;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use unsubscribe_token::UnsubscribeToken;
@@ -0,0 +1,216 @@
+use super::MergeTags;
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+
+/// The body of a newsletter issue: the HTML and plain-text versions sent to
+/// subscribers, plus the Markdown source they were rendered from (if any).
+#[derive(Debug)]
+pub struct NewsletterContent {
+    markdown: Option<String>,
+    html: String,
+    text: String,
+}
+
+impl NewsletterContent {
+    /// Builds the content from a Markdown source when one is provided,
+    /// falling back on hand-written HTML and plain-text versions otherwise.
+    pub fn parse(
+        markdown: Option<String>,
+        html: Option<String>,
+        text: Option<String>,
+    ) -> Result<Self, String> {
+        let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+        match (non_empty(markdown), non_empty(html), non_empty(text)) {
+            (Some(markdown), _, _) => Ok(Self::from_markdown(markdown)),
+            (None, Some(html), Some(text)) => Ok(Self {
+                markdown: None,
+                html,
+                text,
+            }),
+            _ => Err(
+                "A newsletter needs either a Markdown body or both an HTML and a plain-text body."
+                    .into(),
+            ),
+        }
+    }
+
+    pub fn from_markdown(markdown: String) -> Self {
+        let html = render_html(&markdown);
+        let text = render_text(&markdown);
+        Self {
+            markdown: Some(markdown),
+            html,
+            text,
+        }
+    }
+
+    /// Rebuilds the content of an issue as it was saved, without rendering it again.
+    pub fn from_stored(markdown: Option<String>, html: String, text: String) -> Self {
+        Self {
+            markdown,
+            html,
+            text,
+        }
+    }
+
+    pub fn validate_merge_tags(&self) -> Result<(), String> {
+        MergeTags::validate(&self.html)?;
+        MergeTags::validate(&self.text)
+    }
+
+    pub fn markdown(&self) -> Option<&str> {
+        self.markdown.as_deref()
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES,
+    )
+}
+
+fn render_html(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser(markdown));
+    // Link targets get percent-encoded, which would hide merge tags such as
+    // `[Unsubscribe]({{unsubscribe_url}})` from the delivery worker.
+    let unsafe_html = unsafe_html.replace("%7B%7B", "{{").replace("%7D%7D", "}}");
+    ammonia::clean(&unsafe_html)
+}
+
+/// Renders Markdown as readable plain text: markup is dropped, list items are
+/// bulleted and link targets are spelled out next to their text.
+fn render_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // `None` for bulleted lists, the next item number for ordered ones.
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::List(first_number)) => lists.push(first_number),
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        text.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::End(Tag::Paragraph) => {
+                text.push('\n');
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::End(Tag::Heading(..)) | Event::End(Tag::CodeBlock(_)) => text.push_str("\n\n"),
+            Event::End(Tag::TableCell) => text.push('\t'),
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => text.push('\n'),
+            Event::End(Tag::Table(_)) => text.push('\n'),
+            Event::End(Tag::Link(_, url, _)) | Event::End(Tag::Image(_, url, _)) => {
+                text.push_str(&format!(" ({})", url));
+            }
+            Event::Text(s) | Event::Code(s) => text.push_str(&s),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("---\n\n"),
+            _ => {}
+        }
+    }
+    text.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewsletterContent;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let content = NewsletterContent::from_markdown("# Hello\n\nSome *emphasis*.".into());
+        assert_eq!(
+            content.html(),
+            "<h1>Hello</h1>\n<p>Some <em>emphasis</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn rendered_html_is_sanitized() {
+        let content = NewsletterContent::from_markdown(
+            "Hi <script>alert('pwned')</script>\n\n[click](javascript:alert(1))".into(),
+        );
+        assert!(!content.html().contains("<script>"));
+        assert!(!content.html().contains("javascript:"));
+    }
+
+    #[test]
+    fn merge_tags_survive_in_link_targets() {
+        let content = NewsletterContent::from_markdown("[Unsubscribe]({{unsubscribe_url}})".into());
+        assert!(content.html().contains(r#"href="{{unsubscribe_url}}""#));
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_readable_plain_text() {
+        let content = NewsletterContent::from_markdown(
+            "# Hello\n\nRead [our blog](https://example.com).\n\n* one\n* two\n\n1. first\n2. second"
+                .into(),
+        );
+        assert_eq!(
+            content.text(),
+            "Hello\n\nRead our blog (https://example.com).\n\n- one\n- two\n\n1. first\n2. second"
+        );
+    }
+
+    #[test]
+    fn markdown_takes_precedence_over_explicit_versions() {
+        let content = NewsletterContent::parse(
+            Some("**Bold**".into()),
+            Some("<p>ignored</p>".into()),
+            Some("ignored".into()),
+        )
+        .unwrap();
+        assert_eq!(content.markdown(), Some("**Bold**"));
+        assert_eq!(content.text(), "Bold");
+    }
+
+    #[test]
+    fn explicit_html_and_text_are_kept_as_they_are() {
+        let content = NewsletterContent::parse(
+            Some("".into()),
+            Some("<p>Hand-written</p>".into()),
+            Some("Hand-written".into()),
+        )
+        .unwrap();
+        assert_eq!(content.markdown(), None);
+        assert_eq!(content.html(), "<p>Hand-written</p>");
+        assert_eq!(content.text(), "Hand-written");
+    }
+
+    #[test]
+    fn explicit_html_without_text_is_rejected() {
+        assert_err!(NewsletterContent::parse(
+            None,
+            Some("<p>Hand-written</p>".into()),
+            None
+        ));
+    }
+
+    #[test]
+    fn a_markdown_body_alone_is_enough() {
+        assert_ok!(NewsletterContent::parse(Some("Hi".into()), None, None));
+    }
+}
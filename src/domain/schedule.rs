@@ -0,0 +1,81 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// A point in time expressed as a wall-clock time in a given timezone,
+/// e.g. "Monday 08:00 in Europe/Paris".
+#[derive(Debug)]
+pub struct Schedule {
+    send_at: DateTime<Utc>,
+    timezone: Tz,
+}
+
+impl Schedule {
+    /// Parses a `datetime-local` form value (`2023-07-03T08:00`) interpreted
+    /// in an IANA timezone (`Europe/Paris`).
+    pub fn parse(local_datetime: &str, timezone: &str) -> Result<Self, String> {
+        let timezone: Tz = timezone
+            .parse()
+            .map_err(|_| format!("{} is not a valid timezone.", timezone))?;
+        let local_datetime = NaiveDateTime::parse_from_str(local_datetime, "%Y-%m-%dT%H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(local_datetime, "%Y-%m-%dT%H:%M:%S"))
+            .map_err(|_| format!("{} is not a valid date and time.", local_datetime))?;
+        let send_at = timezone
+            .from_local_datetime(&local_datetime)
+            .single()
+            .ok_or_else(|| {
+                format!(
+                    "{} does not exist or is ambiguous in {}.",
+                    local_datetime, timezone
+                )
+            })?;
+        Ok(Self {
+            send_at: send_at.with_timezone(&Utc),
+            timezone,
+        })
+    }
+
+    pub fn send_at(&self) -> DateTime<Utc> {
+        self.send_at
+    }
+
+    pub fn timezone(&self) -> &str {
+        self.timezone.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schedule;
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_local_time_is_converted_to_utc() {
+        let schedule = Schedule::parse("2023-07-03T08:00", "Europe/Paris").unwrap();
+        assert_eq!(
+            Utc.with_ymd_and_hms(2023, 7, 3, 6, 0, 0).unwrap(),
+            schedule.send_at()
+        );
+        assert_eq!("Europe/Paris", schedule.timezone());
+    }
+
+    #[test]
+    fn seconds_are_accepted() {
+        assert_ok!(Schedule::parse("2023-07-03T08:00:30", "UTC"));
+    }
+
+    #[test]
+    fn an_unknown_timezone_is_rejected() {
+        assert_err!(Schedule::parse("2023-07-03T08:00", "Mars/Olympus_Mons"));
+    }
+
+    #[test]
+    fn an_invalid_date_is_rejected() {
+        assert_err!(Schedule::parse("next monday", "UTC"));
+    }
+
+    #[test]
+    fn a_time_skipped_by_daylight_saving_is_rejected() {
+        assert_err!(Schedule::parse("2023-03-26T02:30", "Europe/Paris"));
+    }
+}
@@ -0,0 +1,89 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Self {
+        let tag = mac(subscriber_id, hmac_secret).finalize().into_bytes();
+        Self(URL_SAFE_NO_PAD.encode(tag))
+    }
+
+    pub fn parse(s: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&s) {
+            Ok(tag) if !tag.is_empty() => Ok(Self(s)),
+            _ => Err(format!("{} is not a valid unsubscribe token.", s)),
+        }
+    }
+
+    pub fn verify(&self, subscriber_id: Uuid, hmac_secret: &Secret<String>) -> bool {
+        let tag = match URL_SAFE_NO_PAD.decode(&self.0) {
+            Ok(tag) => tag,
+            Err(_) => return false,
+        };
+        mac(subscriber_id, hmac_secret).verify_slice(&tag).is_ok()
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// The HMAC secret also signs flash message cookies: prefix the payload so
+// that a tag computed here can never be valid anywhere else.
+fn mac(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claims::assert_err;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_valid_for_its_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        let token = UnsubscribeToken::parse(token.as_ref().to_string()).unwrap();
+        assert!(token.verify(subscriber_id, &secret()));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_another_subscriber() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        assert!(!token.verify(Uuid::new_v4(), &secret()));
+    }
+
+    #[test]
+    fn a_token_is_rejected_with_another_secret() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert!(!token.verify(subscriber_id, &Secret::new("another-key".to_string())));
+    }
+
+    #[test]
+    fn empty_token_is_rejected() {
+        assert_err!(UnsubscribeToken::parse("".to_string()));
+    }
+
+    #[test]
+    fn token_that_is_not_base64_is_rejected() {
+        assert_err!(UnsubscribeToken::parse("not a token!".to_string()));
+    }
+}
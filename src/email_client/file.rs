@@ -0,0 +1,93 @@
+use super::{message_id, mime_message, EmailHeader, EmailSender};
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::PathBuf;
+
+/// Writes every email as an `.eml` file in a directory instead of sending
+/// it, which is handy to inspect outgoing emails during local development.
+pub struct FileEmailClient {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl FileEmailClient {
+    pub fn new(directory: PathBuf, sender: SubscriberEmail) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FileEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = mime_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        let message_id = message_id(&message);
+        self.transport.send(message).await?;
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender, FileEmailClient};
+    use claims::assert_ok;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_in_the_directory() {
+        // Given
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let email_client = FileEmailClient::new(directory.clone(), email()).unwrap();
+        let headers = [EmailHeader::new("List-Unsubscribe", "<http://localhost>")];
+
+        // When
+        let outcome = email_client
+            .send_email(
+                &email(),
+                "Newsletter title",
+                "<p>Newsletter body as html</p>",
+                "Newsletter body as plain text",
+                &headers,
+            )
+            .await;
+
+        // Then
+        let message_id = assert_ok!(outcome).unwrap();
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("Subject: Newsletter title"));
+        assert!(content.contains("List-Unsubscribe: <http://localhost>"));
+        assert!(content.contains(&format!("Message-ID: {}", message_id)));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
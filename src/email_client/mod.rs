@@ -0,0 +1,83 @@
+mod file;
+mod postmark;
+mod rate_limiter;
+mod smtp;
+
+pub use file::FileEmailClient;
+pub use postmark::PostmarkClient;
+pub use rate_limiter::{RateLimited, RateLimitedEmailSender};
+pub use smtp::SmtpClient;
+
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+use serde::Serialize;
+
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Returns the id the provider assigned to the message, if it told us.
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error>;
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    name: String,
+    value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+fn mime_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[EmailHeader],
+) -> Result<Message, anyhow::Error> {
+    let from: Mailbox = sender
+        .as_ref()
+        .parse()
+        .context("Failed to parse the sender address.")?;
+    let to: Mailbox = recipient
+        .as_ref()
+        .parse()
+        .context("Failed to parse the recipient address.")?;
+    let mut message = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .message_id(None);
+    for EmailHeader { name, value } in headers {
+        let name = HeaderName::new_from_ascii(name.clone())
+            .with_context(|| format!("{} is not a valid header name.", name))?;
+        message = message.raw_header(HeaderValue::new(name, value.clone()));
+    }
+    message
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_string(),
+            html_content.to_string(),
+        ))
+        .context("Failed to build the email message.")
+}
+
+fn message_id(message: &Message) -> Option<String> {
+    message.headers().get_raw("Message-ID").map(str::to_owned)
+}
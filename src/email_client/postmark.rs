@@ -1,16 +1,18 @@
+use super::{EmailHeader, EmailSender, RateLimited};
 use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, Secret};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-pub struct EmailClient {
+pub struct PostmarkClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
 }
 
-impl EmailClient {
+impl PostmarkClient {
     pub fn new(
         base_url: String,
         sender: SubscriberEmail,
@@ -24,14 +26,18 @@ impl EmailClient {
             authorization_token,
         }
     }
+}
 
-    pub async fn send_email(
+#[async_trait::async_trait]
+impl EmailSender for PostmarkClient {
+    async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
         let url = format!("{}/email", self.base_url);
         let request_body = SendEmailRequest {
             from: self.sender.as_ref(),
@@ -39,8 +45,10 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            headers,
         };
-        self.http_client
+        let response = self
+            .http_client
             .post(&url)
             .header(
                 "X-Postmark-Server-Token",
@@ -48,12 +56,34 @@ impl EmailClient {
             )
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+            .await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            // Only the delay in seconds is supported, not the HTTP date.
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.trim().parse().ok())
+                .map(std::time::Duration::from_secs);
+            return Err(RateLimited { retry_after }.into());
+        }
+        let response = response.error_for_status()?;
+        // The email went out even if we can't make sense of the response body.
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.message_id);
+        Ok(message_id)
     }
 }
 
+#[derive(Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -62,12 +92,14 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
 }
 
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailHeader, EmailSender, PostmarkClient, RateLimited};
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
@@ -105,8 +137,8 @@ mod tests {
         SubscriberEmail::parse(SafeEmail().fake()).unwrap()
     }
 
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
+    fn email_client(base_url: String) -> PostmarkClient {
+        PostmarkClient::new(
             base_url,
             email(),
             Secret::new(Faker.fake()),
@@ -132,13 +164,46 @@ mod tests {
 
         // When
         let _ = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Then
         // Mock expectations are implicitly checked on drop
     }
 
+    #[tokio::test]
+    async fn send_email_forwards_custom_headers() {
+        // Given
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // When
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        // Then
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([{
+                "Name": "List-Unsubscribe-Post",
+                "Value": "List-Unsubscribe=One-Click"
+            }])
+        );
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         // Given
@@ -153,13 +218,43 @@ mod tests {
 
         // When
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Then
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_postmark_message_id() {
+        // Given
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2023-07-04T10:00:00.0000000Z",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // When
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Then
+        assert_eq!(
+            outcome.unwrap().as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         // Given
@@ -174,13 +269,39 @@ mod tests {
 
         // When
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Then
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_is_rate_limited_if_the_server_returns_429() {
+        // Given
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // When
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Then
+        let error = outcome.unwrap_err();
+        let rate_limited = error.downcast_ref::<RateLimited>().unwrap();
+        assert_eq!(
+            rate_limited.retry_after,
+            Some(std::time::Duration::from_secs(7))
+        );
+    }
+
     #[tokio::test]
     async fn send_email_times_out_if_the_server_takes_too_long() {
         // Given
@@ -196,7 +317,7 @@ mod tests {
 
         // When
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Then
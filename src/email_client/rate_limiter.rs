@@ -0,0 +1,269 @@
+use super::{EmailHeader, EmailSender};
+use crate::configuration::RateLimitSettings;
+use crate::domain::SubscriberEmail;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The provider, or the limiter itself, refused to send the email for now:
+/// it should be tried again later, without counting as a failed attempt.
+#[derive(thiserror::Error, Debug)]
+#[error("Too many emails sent, try again later.")]
+pub struct RateLimited {
+    /// When the provider told us how long to wait.
+    pub retry_after: Option<Duration>,
+}
+
+/// Lets `per_second` emails through on average, in bursts of up to a second's worth.
+struct TokenBucket {
+    per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_second: f64, now: Instant) -> Self {
+        let capacity = per_second.max(1.0);
+        Self {
+            per_second,
+            capacity,
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    /// Takes a token, or tells how long until there is one.
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.per_second).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.per_second,
+            ))
+        }
+    }
+}
+
+struct Limits {
+    global: TokenBucket,
+    domains: HashMap<String, TokenBucket>,
+    /// Set when the provider asked us to slow down.
+    paused_until: Option<Instant>,
+}
+
+/// The longest a send waits for a token of the global limit before giving up.
+const MAX_GLOBAL_WAIT: Duration = Duration::from_secs(1);
+
+/// Throttles the emails sent through another `EmailSender`, shared by all the
+/// delivery workers of the process.
+///
+/// A send waits for the next token of the global limit only if it comes
+/// within `MAX_GLOBAL_WAIT`. Otherwise, as when going over the limit of the
+/// recipient's domain or while the provider asked us to slow down, it fails
+/// with `RateLimited`: the worker puts the task back in the queue instead of
+/// sleeping on its transaction, and moves on.
+pub struct RateLimitedEmailSender {
+    inner: Arc<dyn EmailSender>,
+    limits: Mutex<Limits>,
+}
+
+impl RateLimitedEmailSender {
+    pub fn new(inner: Arc<dyn EmailSender>, settings: &RateLimitSettings) -> Self {
+        let now = Instant::now();
+        let domains = settings
+            .domains
+            .iter()
+            .map(|(domain, &per_second)| (domain.to_lowercase(), TokenBucket::new(per_second, now)))
+            .collect();
+        Self {
+            inner,
+            limits: Mutex::new(Limits {
+                global: TokenBucket::new(settings.messages_per_second, now),
+                domains,
+                paused_until: None,
+            }),
+        }
+    }
+
+    fn limits(&self) -> std::sync::MutexGuard<'_, Limits> {
+        // The limits stay consistent even if a thread panicked with the lock.
+        self.limits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take_domain_token(&self, recipient: &SubscriberEmail) -> Result<(), RateLimited> {
+        let domain = recipient
+            .as_ref()
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .unwrap_or_default();
+        match self.limits().domains.get_mut(&domain) {
+            Some(bucket) => bucket.try_take(Instant::now()).map_err(|wait| RateLimited {
+                retry_after: Some(wait),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    async fn take_global_token(&self) -> Result<(), RateLimited> {
+        loop {
+            let wait = {
+                let mut limits = self.limits();
+                let now = Instant::now();
+                match limits.paused_until {
+                    Some(until) if until > now => {
+                        return Err(RateLimited {
+                            retry_after: Some(until - now),
+                        })
+                    }
+                    _ => match limits.global.try_take(now) {
+                        Ok(()) => return Ok(()),
+                        Err(wait) if wait > MAX_GLOBAL_WAIT => {
+                            return Err(RateLimited {
+                                retry_after: Some(wait),
+                            })
+                        }
+                        Err(wait) => wait,
+                    },
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for RateLimitedEmailSender {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        self.take_domain_token(recipient)?;
+        self.take_global_token().await?;
+        let outcome = self
+            .inner
+            .send_email(recipient, subject, html_content, text_content, headers)
+            .await;
+        if let Err(e) = &outcome {
+            if let Some(RateLimited {
+                retry_after: Some(retry_after),
+            }) = e.downcast_ref()
+            {
+                // Nothing will go through until then: every worker backs off.
+                self.limits().paused_until = Some(Instant::now() + *retry_after);
+            }
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimited, RateLimitedEmailSender, TokenBucket};
+    use crate::configuration::RateLimitSettings;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender};
+    use claims::{assert_err, assert_ok};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn a_bucket_lets_a_burst_through_then_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, start);
+        assert_ok!(bucket.try_take(start));
+        assert_ok!(bucket.try_take(start));
+        assert_eq!(bucket.try_take(start), Err(Duration::from_millis(500)));
+        assert_ok!(bucket.try_take(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn a_bucket_slower_than_one_per_second_still_lets_one_through() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(0.5, start);
+        assert_ok!(bucket.try_take(start));
+        assert_eq!(bucket.try_take(start), Err(Duration::from_secs(2)));
+    }
+
+    struct NoopSender;
+
+    #[async_trait::async_trait]
+    impl EmailSender for NoopSender {
+        async fn send_email(
+            &self,
+            _recipient: &SubscriberEmail,
+            _subject: &str,
+            _html_content: &str,
+            _text_content: &str,
+            _headers: &[EmailHeader],
+        ) -> Result<Option<String>, anyhow::Error> {
+            Ok(None)
+        }
+    }
+
+    async fn send(sender: &RateLimitedEmailSender, recipient: &str) -> Result<(), anyhow::Error> {
+        let recipient = SubscriberEmail::parse(recipient.to_string()).unwrap();
+        sender
+            .send_email(&recipient, "Subject", "<p>Body</p>", "Body", &[])
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn going_over_the_limit_of_a_domain_is_rate_limited() {
+        let settings = RateLimitSettings {
+            messages_per_second: 100.0,
+            domains: [("gmail.com".to_string(), 1.0)].into(),
+        };
+        let sender = RateLimitedEmailSender::new(Arc::new(NoopSender), &settings);
+
+        assert_ok!(send(&sender, "ursula@gmail.com").await);
+        let outcome = send(&sender, "victor@GMAIL.com").await;
+        assert_err!(&outcome);
+        assert!(outcome.unwrap_err().downcast_ref::<RateLimited>().is_some());
+        assert_ok!(send(&sender, "victor@outlook.com").await);
+    }
+
+    fn rate_limited_for(outcome: Result<(), anyhow::Error>) -> Option<Duration> {
+        outcome
+            .unwrap_err()
+            .downcast_ref::<RateLimited>()
+            .expect("The email should have been rate limited.")
+            .retry_after
+    }
+
+    #[tokio::test]
+    async fn a_long_wait_for_the_global_limit_is_rate_limited_instead() {
+        let settings = RateLimitSettings {
+            messages_per_second: 0.5,
+            domains: HashMap::new(),
+        };
+        let sender = RateLimitedEmailSender::new(Arc::new(NoopSender), &settings);
+
+        assert_ok!(send(&sender, "ursula@gmail.com").await);
+        let retry_after = rate_limited_for(send(&sender, "victor@outlook.com").await).unwrap();
+        assert!(retry_after > Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn sends_are_rate_limited_while_the_provider_asked_to_slow_down() {
+        let settings = RateLimitSettings {
+            messages_per_second: 100.0,
+            domains: HashMap::new(),
+        };
+        let sender = RateLimitedEmailSender::new(Arc::new(NoopSender), &settings);
+        sender.limits().paused_until = Some(Instant::now() + Duration::from_secs(60));
+
+        let retry_after = rate_limited_for(send(&sender, "ursula@gmail.com").await).unwrap();
+        assert!(retry_after > Duration::from_secs(50));
+    }
+}
@@ -0,0 +1,193 @@
+use super::{message_id, mime_message, EmailHeader, EmailSender};
+use crate::domain::SubscriberEmail;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+pub struct SmtpClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, Secret<String>)>,
+        require_tls: bool,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        // Without TLS we talk plain SMTP, which is only meant for local
+        // stand-ins such as MailHog.
+        let mut builder = if require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder =
+                builder.credentials(Credentials::new(username, password.expose_secret().clone()));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = mime_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        let message_id = message_id(&message);
+        self.transport.send(message).await?;
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender, SmtpClient};
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::Fake;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A minimal SMTP server accepting a single session and recording the
+    /// DATA it receives.
+    struct SmtpStandIn {
+        port: u16,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SmtpStandIn {
+        async fn start(rcpt_reply: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let messages = received.clone();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                let mut data: Option<String> = None;
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Some(body) = data.as_mut() {
+                        if line == "." {
+                            messages.lock().unwrap().push(data.take().unwrap());
+                            writer.write_all(b"250 Queued\r\n").await.unwrap();
+                        } else {
+                            body.push_str(&line);
+                            body.push('\n');
+                        }
+                        continue;
+                    }
+                    let reply: &[u8] = match line.to_uppercase().get(..4) {
+                        Some("EHLO") | Some("HELO") => b"250 localhost\r\n",
+                        Some("MAIL") => b"250 OK\r\n",
+                        Some("RCPT") => rcpt_reply.as_bytes(),
+                        Some("DATA") => {
+                            data = Some(String::new());
+                            b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                        }
+                        Some("QUIT") => {
+                            writer.write_all(b"221 Bye\r\n").await.unwrap();
+                            break;
+                        }
+                        _ => b"250 OK\r\n",
+                    };
+                    writer.write_all(reply).await.unwrap();
+                }
+            });
+            Self { port, received }
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn smtp_client(port: u16) -> SmtpClient {
+        SmtpClient::new(
+            "127.0.0.1",
+            port,
+            None,
+            false,
+            email(),
+            std::time::Duration::from_millis(500),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_multipart_message_with_custom_headers() {
+        // Given
+        let server = SmtpStandIn::start("250 OK\r\n").await;
+        let email_client = smtp_client(server.port);
+        let subject: String = Sentence(1..2).fake();
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+
+        // When
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject,
+                &Paragraph(1..10).fake::<String>(),
+                &Paragraph(1..10).fake::<String>(),
+                &headers,
+            )
+            .await;
+
+        // Then
+        assert_ok!(outcome);
+        let received = server.received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(received[0].contains("multipart/alternative"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_rejects_the_recipient() {
+        // Given
+        let server = SmtpStandIn::start("550 No such user\r\n").await;
+        let email_client = smtp_client(server.port);
+
+        // When
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &Sentence(1..2).fake::<String>(),
+                &Paragraph(1..10).fake::<String>(),
+                &Paragraph(1..10).fake::<String>(),
+                &[],
+            )
+            .await;
+
+        // Then
+        assert_err!(outcome);
+    }
+}
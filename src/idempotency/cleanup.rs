@@ -0,0 +1,83 @@
+use crate::configuration::{IdempotencySettings, Settings};
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::time::Duration;
+
+async fn cleanup_loop(
+    pool: PgPool,
+    settings: IdempotencySettings,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        // Failures are already logged: we just try again at the next round.
+        let _ = purge_expired_keys(&pool, &settings).await;
+        tokio::select! {
+            _ = tokio::time::sleep(settings.cleanup_interval()) => {}
+            _ = shutdown.requested() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Deletes every expired key, one batch at a time so that no single query
+/// holds locks on a large part of the table.
+#[tracing::instrument(skip_all, err)]
+pub async fn purge_expired_keys(
+    pool: &PgPool,
+    settings: &IdempotencySettings,
+) -> Result<u64, anyhow::Error> {
+    let expired_before = Utc::now() - chrono::Duration::from_std(settings.ttl())?;
+    let mut n_purged_keys = 0;
+    loop {
+        let n_deleted_rows =
+            delete_expired_batch(pool, expired_before, settings.cleanup_batch_size).await?;
+        n_purged_keys += n_deleted_rows;
+        if n_deleted_rows < settings.cleanup_batch_size as u64 {
+            break;
+        }
+        // Give the requests in flight a chance to get their locks.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tracing::info!(
+        purged_keys = n_purged_keys,
+        "Expired idempotency keys have been purged."
+    );
+    Ok(n_purged_keys)
+}
+
+/// Keys locked by a request in flight are skipped: they'll be purged
+/// at the next round if they are still expired by then.
+async fn delete_expired_batch(
+    pool: &PgPool,
+    expired_before: chrono::DateTime<Utc>,
+    batch_size: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE (user_id, idempotency_key) IN (
+            SELECT user_id, idempotency_key
+            FROM idempotency
+            WHERE created_at < $1
+            LIMIT $2
+            FOR UPDATE
+            SKIP LOCKED
+        )
+        "#,
+        expired_before,
+        batch_size
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn run_idempotency_cleanup_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    cleanup_loop(connection_pool, configuration.idempotency, shutdown).await
+}
@@ -0,0 +1,90 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+/// A digest of what a request asks for, to tell apart a retry from a
+/// different request that reuses the same idempotency key by mistake.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestFingerprint(String);
+
+impl RequestFingerprint {
+    /// Fields that don't change the outcome of the request, e.g. the
+    /// idempotency key itself, must be left out of `payload`.
+    ///
+    /// Going through a `serde_json::Value` sorts the fields by name: the
+    /// fingerprint doesn't depend on the order they are declared in.
+    pub fn of<T: serde::Serialize>(payload: &T) -> Result<Self, anyhow::Error> {
+        let payload = serde_json::to_vec(&serde_json::to_value(payload)?)?;
+        Ok(Self(format!("{:x}", Sha256::digest(payload))))
+    }
+
+    /// For payloads that may carry secrets, e.g. passwords: a plain digest
+    /// of them could be brute-forced back out of the database.
+    pub fn keyed<T: serde::Serialize>(
+        payload: &T,
+        hmac_secret: &Secret<String>,
+    ) -> Result<Self, anyhow::Error> {
+        let payload = serde_json::to_vec(&serde_json::to_value(payload)?)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"idempotency:");
+        mac.update(&payload);
+        Ok(Self(format!("{:x}", mac.finalize().into_bytes())))
+    }
+}
+
+impl AsRef<str> for RequestFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestFingerprint;
+    use secrecy::Secret;
+    use serde_json::json;
+
+    #[test]
+    fn the_order_of_the_fields_does_not_matter() {
+        #[derive(serde::Serialize)]
+        struct Payload {
+            title: &'static str,
+            content: &'static str,
+        }
+        let payload = Payload {
+            title: "Hi",
+            content: "Hello",
+        };
+        assert_eq!(
+            RequestFingerprint::of(&payload).unwrap(),
+            RequestFingerprint::of(&json!({ "content": "Hello", "title": "Hi" })).unwrap()
+        );
+    }
+
+    #[test]
+    fn keyed_fingerprints_depend_on_the_secret() {
+        let payload = json!({ "new_password": "hunter2" });
+        let secret = |s: &str| Secret::new(s.to_string());
+        assert_eq!(
+            RequestFingerprint::keyed(&payload, &secret("a")).unwrap(),
+            RequestFingerprint::keyed(&payload, &secret("a")).unwrap()
+        );
+        assert_ne!(
+            RequestFingerprint::keyed(&payload, &secret("a")).unwrap(),
+            RequestFingerprint::keyed(&payload, &secret("b")).unwrap()
+        );
+        assert_ne!(
+            RequestFingerprint::keyed(&payload, &secret("a")).unwrap(),
+            RequestFingerprint::of(&payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn a_different_payload_has_a_different_fingerprint() {
+        assert_ne!(
+            RequestFingerprint::of(&json!({ "title": "Hi" })).unwrap(),
+            RequestFingerprint::of(&json!({ "title": "Hello" })).unwrap()
+        );
+    }
+}
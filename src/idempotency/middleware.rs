@@ -0,0 +1,139 @@
+use super::{
+    in_progress_response, payload_mismatch_response, save_response, try_processing, IdempotencyKey,
+    NextAction, RequestFingerprint,
+};
+use crate::authentication::UserId;
+use crate::configuration::IdempotencySettings;
+use crate::startup::HmacSecret;
+use crate::utils::{e400, e500};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{web, FromRequest, HttpMessage};
+use actix_web_lab::middleware::Next;
+use sqlx::PgPool;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+/// What two requests must share for the second one to be a retry of the first.
+#[derive(serde::Serialize)]
+struct FingerprintedRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    /// Form fields, without the idempotency key.
+    form: Option<Vec<(String, String)>>,
+    /// Any other kind of body.
+    body: Option<String>,
+}
+
+/// Makes retries of the wrapped routes safe: a request carrying an idempotency
+/// key, in the `Idempotency-Key` header or the `idempotency_key` form field, is
+/// processed once and its retries get the saved response. Requests without a
+/// key go through untouched.
+///
+/// It must be wrapped by a middleware that authenticates the user, since keys
+/// are scoped to them.
+///
+/// The handler runs in its own transactions: a crash between its commit and
+/// the response being saved leaves the key free, and a retry is processed
+/// again. Server errors are not saved either. Flash messages are not part of
+/// the saved response: a retry only gets the redirection.
+/// Handlers that need stronger guarantees save the response within their own
+/// transaction, see `publish_newsletter`.
+pub async fn idempotent(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let body = {
+        let (http_request, payload) = req.parts_mut();
+        web::Bytes::from_request(http_request, payload).await?
+    };
+    // The handler still has to read the body.
+    req.set_payload(Payload::from(body.clone()));
+
+    let is_form = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.starts_with("application/x-www-form-urlencoded"));
+    let mut form_key = None;
+    let form = if is_form {
+        let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).map_err(e400)?;
+        let (keys, fields) = fields
+            .into_iter()
+            .partition::<Vec<_>, _>(|(name, _)| name == IDEMPOTENCY_KEY_FIELD);
+        form_key = keys.into_iter().next().map(|(_, value)| value);
+        Some(fields)
+    } else {
+        None
+    };
+    let header_key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|h| h.to_str().map(ToOwned::to_owned))
+        .transpose()
+        .map_err(e400)?;
+    let idempotency_key: IdempotencyKey = match header_key.or(form_key) {
+        Some(key) => key.try_into().map_err(e400)?,
+        None => return next.call(req).await.map(|r| r.map_into_boxed_body()),
+    };
+
+    let user_id = match req.extensions().get::<UserId>() {
+        Some(user_id) => **user_id,
+        None => {
+            return Err(e500(
+                "Idempotency keys require the user to be authenticated first.",
+            ))
+        }
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The database pool is registered as application data")
+        .clone();
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .expect("The idempotency settings are registered as application data")
+        .clone();
+    let hmac_secret = req
+        .app_data::<web::Data<HmacSecret>>()
+        .expect("The HMAC secret is registered as application data")
+        .clone();
+    let fingerprint = RequestFingerprint::keyed(
+        &FingerprintedRequest {
+            method: req.method().as_str(),
+            path: req.path(),
+            body: form
+                .is_none()
+                .then(|| String::from_utf8_lossy(&body).into_owned()),
+            form,
+        },
+        &hmac_secret.0,
+    )
+    .map_err(e500)?;
+
+    let transaction =
+        match try_processing(&pool, &idempotency_key, &fingerprint, user_id, &settings)
+            .await
+            .map_err(e500)?
+        {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => {
+                return Ok(req.into_response(saved_response))
+            }
+            NextAction::InProgress => return Ok(req.into_response(in_progress_response(&settings))),
+            NextAction::PayloadMismatch => {
+                return Ok(req.into_response(payload_mismatch_response()))
+            }
+        };
+    let response = next.call(req).await?.map_into_boxed_body();
+    if response.status().is_server_error() {
+        // Dropping the transaction releases the key for a retry.
+        return Ok(response);
+    }
+    let (request, response) = response.into_parts();
+    let response = save_response(transaction, &idempotency_key, user_id, response)
+        .await
+        .map_err(e500)?;
+    Ok(ServiceResponse::new(request, response))
+}
@@ -1,5 +1,14 @@
+mod cleanup;
+mod fingerprint;
 mod key;
+mod middleware;
 mod persistence;
 
+pub use cleanup::{purge_expired_keys, run_idempotency_cleanup_until_stopped};
+pub use fingerprint::RequestFingerprint;
 pub use key::IdempotencyKey;
-pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
+pub use middleware::idempotent;
+pub use persistence::{
+    get_saved_response, in_progress_response, payload_mismatch_response, save_response,
+    try_processing, NextAction,
+};
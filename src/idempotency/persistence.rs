@@ -1,5 +1,8 @@
-use super::IdempotencyKey;
+use super::{IdempotencyKey, RequestFingerprint};
+use crate::configuration::IdempotencySettings;
+use actix_web::http::header::RETRY_AFTER;
 use actix_web::{body::to_bytes, HttpResponse};
+use chrono::Utc;
 use reqwest::StatusCode;
 use sqlx::{postgres::PgHasArrayType, PgPool};
 use sqlx::{Postgres, Transaction};
@@ -16,40 +19,130 @@ struct HeaderPairRecord {
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
+    /// Another request with the same key is still being processed, and
+    /// didn't complete within `IdempotencySettings::concurrent_request_wait`.
+    InProgress,
+    /// The key was already used for a request with a different payload.
+    PayloadMismatch,
 }
 
+/// A key older than the TTL is treated as fresh: its saved response is
+/// dropped and the request is processed again.
+///
+/// While a request is being processed, its row stays locked: a concurrent
+/// request with the same key waits for it to complete (up to a point) and
+/// then replays its response.
+///
+/// Reusing a key for a request with a different fingerprint is a client bug:
+/// it is reported rather than answered with the response of the first request.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
+    request_fingerprint: &RequestFingerprint,
     user_id: Uuid,
+    settings: &IdempotencySettings,
 ) -> Result<NextAction, anyhow::Error> {
+    let expired_before = Utc::now() - chrono::Duration::from_std(settings.ttl())?;
     let mut transaction = pool.begin().await?;
-    let n_inserted_rows = sqlx::query!(
+    // A zero lock timeout would mean waiting forever: 1ms is as good as
+    // not waiting at all.
+    let lock_timeout = settings.concurrent_request_wait().as_millis().max(1);
+    sqlx::query("SELECT set_config('lock_timeout', $1, true)")
+        .bind(format!("{lock_timeout}ms"))
+        .execute(&mut transaction)
+        .await?;
+    let n_inserted_rows = match sqlx::query!(
         r#"
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
+            request_fingerprint,
             created_at
             )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
+        VALUES ($1, $2, $4, now())
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET
+            created_at = now(),
+            request_fingerprint = $4,
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL
+        WHERE idempotency.created_at < $3
         "#,
         user_id,
-        idempotency_key.as_ref()
+        idempotency_key.as_ref(),
+        expired_before,
+        request_fingerprint.as_ref()
     )
     .execute(&mut transaction)
-    .await?
-    .rows_affected();
+    .await
+    {
+        Ok(result) => result.rows_affected(),
+        Err(e) if is_lock_timeout(&e) => return Ok(NextAction::InProgress),
+        Err(e) => return Err(e.into()),
+    };
+    // The lock timeout only guards the wait for concurrent requests: it must
+    // not get in the way of the processing itself.
+    sqlx::query("SELECT set_config('lock_timeout', '0', true)")
+        .execute(&mut transaction)
+        .await?;
     if n_inserted_rows > 0 {
         Ok(NextAction::StartProcessing(transaction))
+    } else if !fingerprint_matches(pool, idempotency_key, request_fingerprint, user_id).await? {
+        Ok(NextAction::PayloadMismatch)
     } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("We expectd a saved response, we didn't find it"))?;
-        Ok(NextAction::ReturnSavedResponse(saved_response))
+        match get_saved_response(pool, idempotency_key, user_id).await? {
+            Some(saved_response) => Ok(NextAction::ReturnSavedResponse(saved_response)),
+            None => Ok(NextAction::InProgress),
+        }
+    }
+}
+
+/// Keys saved before fingerprints were recorded match any request.
+async fn fingerprint_matches(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    request_fingerprint: &RequestFingerprint,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let matches = sqlx::query!(
+        r#"
+        SELECT (request_fingerprint IS NULL OR request_fingerprint = $3) as "matches!"
+        FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        request_fingerprint.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(matches.is_none_or(|r| r.matches))
+}
+
+fn is_lock_timeout(e: &sqlx::Error) -> bool {
+    const LOCK_NOT_AVAILABLE: &str = "55P03";
+    match e {
+        sqlx::Error::Database(e) => e.code().as_deref() == Some(LOCK_NOT_AVAILABLE),
+        _ => false,
     }
 }
 
+/// The answer to a request whose key is used by another one in flight.
+pub fn in_progress_response(settings: &IdempotencySettings) -> HttpResponse {
+    HttpResponse::Conflict()
+        .insert_header((RETRY_AFTER, settings.retry_after().as_secs()))
+        .body("A request with the same idempotency key is still being processed.")
+}
+
+/// The answer to a request that reuses a key with a different payload.
+pub fn payload_mismatch_response() -> HttpResponse {
+    HttpResponse::UnprocessableEntity()
+        .body("The idempotency key was already used for a different request.")
+}
+
 pub async fn get_saved_response(
     pool: &PgPool,
     indempotency_key: &IdempotencyKey,
@@ -64,7 +157,8 @@ pub async fn get_saved_response(
         FROM idempotency
         WHERE
             user_id = $1 AND
-            idempotency_key = $2
+            idempotency_key = $2 AND
+            response_status_code IS NOT NULL
         "#,
         user_id,
         indempotency_key.as_ref()
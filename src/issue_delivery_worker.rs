@@ -1,9 +1,18 @@
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    configuration::{IssueDeliverySettings, Settings},
+    domain::{MergeTags, SubscriberEmail},
+    email_client::{EmailHeader, EmailSender, RateLimited},
+    routes::{archive_link, unsubscribe_link},
+    shutdown::Shutdown,
 };
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::postgres::{PgListener, PgPoolOptions};
 use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 use tracing::{field::display, Span};
 use uuid::Uuid;
 
@@ -11,69 +20,309 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    slug: Option<String>,
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    enqueued_at: DateTime<Utc>,
+}
+
+/// What eventually happened to a delivery task, as recorded in `issue_delivery_log`.
+enum DeliveryOutcome<'a> {
+    Sent { message_id: Option<String> },
+    Failed { error: &'a anyhow::Error },
+    SkippedInvalidEmail { error: String },
+    SkippedUnsubscribed,
+}
+
+impl DeliveryOutcome<'_> {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent { .. } => "sent",
+            DeliveryOutcome::Failed { .. } => "failed",
+            DeliveryOutcome::SkippedInvalidEmail { .. } => "skipped_invalid_email",
+            DeliveryOutcome::SkippedUnsubscribed => "skipped_unsubscribed",
+        }
+    }
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// Notified whenever delivery tasks are enqueued.
+const QUEUE_CHANNEL: &str = "issue_delivery_queue";
+
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    settings: IssueDeliverySettings,
+    mut wake_up: watch::Receiver<bool>,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    // A task in flight is always completed (and committed) before stopping.
+    while !shutdown.is_requested() {
+        // Marked as seen before checking the queue, so that a task enqueued
+        // in between still wakes the worker up.
+        wake_up.borrow_and_update();
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &base_url,
+            &hmac_secret,
+            &settings,
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                wait_for_tasks(&pool, &mut wake_up, &settings, &mut shutdown).await;
             }
             Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = shutdown.requested() => {}
+                }
             }
             Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
+    Ok(())
+}
+
+/// Wakes every idle worker up whenever tasks are enqueued, through `wake_up`,
+/// which also tells whether workers can rely on it or have to poll the queue.
+///
+/// A single connection listens on behalf of all the workers of the process.
+async fn listen_until_stopped(
+    pool: PgPool,
+    wake_up: watch::Sender<bool>,
+    settings: IssueDeliverySettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        let mut listener = match listen_to_queue(&pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to listen for delivery tasks, falling back on polling the queue"
+                );
+                tokio::time::sleep(settings.poll_interval()).await;
+                continue;
+            }
+        };
+        // Tasks may have been enqueued while nobody was listening.
+        wake_up.send_replace(true);
+        loop {
+            match listener.try_recv().await {
+                Ok(Some(_)) => {}
+                // The connection was lost, along with any notification sent
+                // meanwhile: the listener reconnects on the next call.
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Stopped listening for delivery tasks, falling back on polling the queue"
+                    );
+                    wake_up.send_replace(false);
+                    break;
+                }
+            }
+            wake_up.send_replace(true);
+        }
+    }
+}
+
+async fn listen_to_queue(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(QUEUE_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Returns once there may be a task to execute: when one is enqueued, or when
+/// one that was retried or requeued with a delay is due. Or on shutdown.
+///
+/// While nobody listens for enqueued tasks, it polls the queue instead.
+async fn wait_for_tasks(
+    pool: &PgPool,
+    wake_up: &mut watch::Receiver<bool>,
+    settings: &IssueDeliverySettings,
+    shutdown: &mut Shutdown,
+) {
+    let next_task_in = match time_until_next_task(pool).await {
+        Ok(next_task_in) => next_task_in,
+        Err(_) => Some(settings.poll_interval()),
+    };
+    let is_listening = *wake_up.borrow();
+    let wait = if is_listening {
+        next_task_in
+    } else {
+        let poll_interval = settings.poll_interval();
+        Some(next_task_in.map_or(poll_interval, |d| d.min(poll_interval)))
+    };
+    let timeout = async {
+        match wait {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = wake_up.changed() => {}
+        _ = timeout => {}
+        _ = shutdown.requested() => {}
+    }
+}
+
+/// `None` if no task is waiting to be executed later.
+#[tracing::instrument(skip_all)]
+async fn time_until_next_task(pool: &PgPool) -> Result<Option<Duration>, anyhow::Error> {
+    let next_task_at = sqlx::query!(
+        r#"
+        SELECT MIN(execute_after) as next_task_at
+        FROM issue_delivery_queue
+        WHERE execute_after > now()
+        "#,
+    )
+    .fetch_one(pool)
+    .await?
+    .next_task_at;
+    Ok(next_task_at.map(|at| (at - Utc::now()).to_std().unwrap_or_default()))
 }
 
 #[tracing::instrument(
     skip_all,
     fields(
         newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        subscriber_email=tracing::field::Empty,
+        n_retries=tracing::field::Empty
     ),
     err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    settings: &IssueDeliverySettings,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (transaction, task) = task.unwrap();
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                        Skipping."
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email))
+        .record("n_retries", task.n_retries);
+    let outcome = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => match get_confirmed_subscriber(pool, email.as_ref()).await? {
+            Some(subscriber) => {
+                let issue = get_issue(pool, task.newsletter_issue_id).await?;
+                let unsubscribe_link = unsubscribe_link(base_url, subscriber.id, hmac_secret);
+                let headers = [
+                    EmailHeader::new("List-Unsubscribe", format!("<{}>", unsubscribe_link)),
+                    EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+                ];
+                let merge_tags = MergeTags {
+                    name: subscriber.name,
+                    email: email.as_ref().to_owned(),
+                    unsubscribe_url: unsubscribe_link.clone(),
+                    subscribed_at: subscriber.subscribed_at.format("%Y-%m-%d").to_string(),
+                };
+                let (html_footer, text_footer) = match &issue.slug {
+                    Some(slug) => {
+                        let archive_link = archive_link(base_url, slug);
+                        (
+                            format!(
+                                r#"<p><a href="{}">View online</a> | <a href="{}">Unsubscribe</a></p>"#,
+                                archive_link, unsubscribe_link
+                            ),
+                            format!(
+                                "View online: {}\nUnsubscribe: {}",
+                                archive_link, unsubscribe_link
+                            ),
+                        )
+                    }
+                    None => (
+                        format!(r#"<p><a href="{}">Unsubscribe</a></p>"#, unsubscribe_link),
+                        format!("Unsubscribe: {}", unsubscribe_link),
+                    ),
+                };
+                match email_client
+                    .send_email(
+                        &email,
+                        &merge_tags.render_text(&issue.title),
+                        &format!(
+                            "{}{}",
+                            merge_tags.render_html(&issue.html_content),
+                            html_footer
+                        ),
+                        &format!(
+                            "{}\n\n{}",
+                            merge_tags.render_text(&issue.text_content),
+                            text_footer
+                        ),
+                        &headers,
+                    )
+                    .await
+                {
+                    Ok(message_id) => DeliveryOutcome::Sent { message_id },
+                    Err(e) if e.is::<RateLimited>() => {
+                        let retry_after = e
+                            .downcast_ref::<RateLimited>()
+                            .and_then(|r| r.retry_after)
+                            .unwrap_or_else(|| settings.backoff(0));
+                        tracing::info!(
+                            retry_after = ?retry_after,
+                            "Sending too many emails. Postponing the delivery."
+                        );
+                        postpone_task(transaction, &task, retry_after).await?;
+                        return Ok(ExecutionOutcome::TaskCompleted);
+                    }
+                    Err(e) => {
+                        if task.n_retries < settings.max_retries {
+                            tracing::warn!(
+                                error.cause_chain = ?e,
+                                error.message = %e,
+                                "Failed to deliver issue to a confirmed subscriber. \
+                                    Retrying later."
+                            );
+                            let backoff = settings.backoff(task.n_retries);
+                            schedule_retry(transaction, &task, backoff).await?;
+                        } else {
+                            tracing::error!(
+                                error.cause_chain = ?e,
+                                error.message = %e,
+                                "Failed to deliver issue to a confirmed subscriber. \
+                                    Giving up after too many retries."
+                            );
+                            move_to_dead_letter(transaction, &task, &e).await?;
+                        }
+                        return Ok(ExecutionOutcome::TaskCompleted);
+                    }
+                }
+            }
+            None => {
+                tracing::info!(
+                    "Skipping a subscriber who is no longer confirmed. \
+                        They unsubscribed after the issue was published."
                 );
+                DeliveryOutcome::SkippedUnsubscribed
             }
-        }
+        },
         Err(e) => {
             tracing::error!(
                 error.cause_chain = ?e,
@@ -81,21 +330,56 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. \
                     Their stored contact details are invalid",
             );
+            DeliveryOutcome::SkippedInvalidEmail { error: e }
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
+    complete_task(transaction, &task, outcome).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue(
+            newsletter_issue_id,
+            subscriber_email)
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    notify_workers(transaction).await
+}
+
+/// Wakes idle workers up once the transaction commits.
+pub async fn notify_workers(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    // The channel can't be a bind parameter of NOTIFY.
+    sqlx::query(&format!("NOTIFY {QUEUE_CHANNEL}"))
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let r = sqlx::query_as!(
+        DeliveryTask,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT newsletter_issue_id, subscriber_email, n_retries, enqueued_at
         FROM issue_delivery_queue
+        WHERE execute_after <= now()
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
@@ -105,22 +389,59 @@ async fn dequeue_task(
     .await?;
 
     if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
+        Ok(Some((transaction, r)))
     } else {
         Ok(None)
     }
 }
 
-#[tracing::instrument(skip_all)]
-async fn delete_task(
+#[tracing::instrument(skip_all, fields(outcome = outcome.as_str()))]
+async fn complete_task(
     mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
+    task: &DeliveryTask,
+    outcome: DeliveryOutcome<'_>,
 ) -> Result<(), anyhow::Error> {
+    let (n_attempts, message_id, error) = match &outcome {
+        DeliveryOutcome::Sent { message_id } => (task.n_retries + 1, message_id.clone(), None),
+        DeliveryOutcome::Failed { error } => {
+            (task.n_retries + 1, None, Some(format!("{:#}", error)))
+        }
+        DeliveryOutcome::SkippedInvalidEmail { error } => {
+            (task.n_retries, None, Some(error.to_owned()))
+        }
+        DeliveryOutcome::SkippedUnsubscribed => (task.n_retries, None, None),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            message_id,
+            error,
+            n_attempts,
+            enqueued_at,
+            completed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            outcome = EXCLUDED.outcome,
+            message_id = EXCLUDED.message_id,
+            error = EXCLUDED.error,
+            n_attempts = issue_delivery_log.n_attempts + EXCLUDED.n_attempts,
+            completed_at = EXCLUDED.completed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        outcome.as_str(),
+        message_id,
+        error,
+        n_attempts,
+        task.enqueued_at
+    )
+    .execute(&mut transaction)
+    .await?;
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
@@ -128,8 +449,61 @@ async fn delete_task(
             newsletter_issue_id = $1 AND
             subscriber_email = $2
         "#,
-        issue_id,
-        email
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(backoff = ?backoff))]
+async fn schedule_retry(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Unlike `schedule_retry`, doesn't count as a failed attempt.
+#[tracing::instrument(skip_all)]
+async fn postpone_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(delay)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after
     )
     .execute(&mut transaction)
     .await?;
@@ -137,12 +511,65 @@ async fn delete_task(
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn move_to_dead_letter(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    error: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_retries = EXCLUDED.n_retries,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries,
+        format!("{:#}", error)
+    )
+    .execute(&mut transaction)
+    .await?;
+    complete_task(transaction, task, DeliveryOutcome::Failed { error }).await
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let r = sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+        SELECT id, name, subscribed_at
+        FROM subscriptions
+        WHERE
+            email = $1 AND
+            status = 'confirmed'
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r)
+}
+
 #[tracing::instrument(skip_all)]
 async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT title, text_content, html_content, slug
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
@@ -154,8 +581,44 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+/// Runs `issue_delivery.n_workers` workers, sharing the connection pool and
+/// the email client, until they have all stopped after a shutdown request.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let settings = configuration.issue_delivery;
+    let n_workers = settings.n_workers.max(1);
+    // Each worker holds a connection for the transaction of its task and
+    // needs another one for its queries, on top of the listener's.
+    let connection_pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(2))
+        .max_connections(2 * n_workers as u32 + 1)
+        .connect_lazy_with(configuration.database.with_db());
+    let email_client = configuration.email_client.rate_limited_client();
+    let (wake_up, wake_up_receiver) = watch::channel(false);
+
+    let mut workers = JoinSet::new();
+    for _ in 0..n_workers {
+        workers.spawn(worker_loop(
+            connection_pool.clone(),
+            email_client.clone(),
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret.clone(),
+            settings.clone(),
+            wake_up_receiver.clone(),
+            shutdown.clone(),
+        ));
+    }
+    let all_workers_stopped = async {
+        while let Some(outcome) = workers.join_next().await {
+            outcome??;
+        }
+        Ok(())
+    };
+    // The listener never stops on its own: it's dropped along with the workers.
+    tokio::select! {
+        outcome = listen_until_stopped(connection_pool.clone(), wake_up, settings.clone()) => outcome,
+        outcome = all_workers_stopped => outcome,
+    }
 }
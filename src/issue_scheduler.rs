@@ -0,0 +1,83 @@
+use crate::{
+    configuration::Settings,
+    domain::IssueSlug,
+    issue_delivery_worker::{enqueue_delivery_tasks, ExecutionOutcome},
+    shutdown::Shutdown,
+    startup::get_connection_pool,
+};
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::{field::display, Span};
+
+async fn scheduler_loop(pool: PgPool, mut shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        let wait = match try_publish_scheduled_issue(&pool).await {
+            Ok(ExecutionOutcome::EmptyQueue) => Duration::from_secs(10),
+            Err(_) => Duration::from_secs(1),
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = shutdown.requested() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Enqueues the delivery of one scheduled issue whose send time has come.
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_publish_scheduled_issue(pool: &PgPool) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title
+        FROM newsletter_issues
+        WHERE
+            status = 'scheduled' AND
+            scheduled_for <= now()
+        ORDER BY scheduled_for
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    if issue.is_none() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    let issue = issue.unwrap();
+    let issue_id = issue.newsletter_issue_id;
+    Span::current().record("newsletter_issue_id", display(issue_id));
+    let slug = IssueSlug::new(&issue.title, issue_id);
+    enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now(),
+            slug = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        slug.as_ref()
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::info!("A scheduled newsletter issue has been published.");
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+pub async fn run_scheduler_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool, shutdown).await
+}
@@ -4,8 +4,10 @@ pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod issue_scheduler;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
@@ -1,8 +1,13 @@
+use std::collections::HashMap;
 use std::fmt::{Debug, Display};
+use std::future::Future;
 
-use tokio::task::JoinError;
+use tokio::task::{JoinError, JoinSet};
 use zero2prod::configuration::get_configuration;
+use zero2prod::idempotency::run_idempotency_cleanup_until_stopped;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::issue_scheduler::run_scheduler_until_stopped;
+use zero2prod::shutdown::{shutdown_channel, shutdown_signal};
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -11,15 +16,91 @@ async fn main() -> anyhow::Result<()> {
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
     let configuration = get_configuration().expect("Failed to read configuration.");
+    let shutdown_timeout = configuration.application.shutdown_timeout();
+    let (shutdown_trigger, shutdown) = shutdown_channel();
     let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+
+    let mut tasks = Tasks::default();
+    tasks.spawn("API", application.run_until_stopped(shutdown.clone()));
+    tasks.spawn(
+        "Background worker",
+        run_worker_until_stopped(configuration.clone(), shutdown.clone()),
+    );
+    tasks.spawn(
+        "Scheduler",
+        run_scheduler_until_stopped(configuration.clone(), shutdown.clone()),
+    );
+    tasks.spawn(
+        "Idempotency cleanup",
+        run_idempotency_cleanup_until_stopped(configuration, shutdown),
+    );
+
+    // None of the tasks stops on its own: if one does, the others go down too.
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
+        signal = shutdown_signal() => {
+            signal?;
+            tracing::info!("Shutting down");
+        }
+        () = tasks.join_next() => {}
     };
+    shutdown_trigger.trigger();
+    let deadline = tokio::time::Instant::now() + shutdown_timeout;
+    while !tasks.is_empty() {
+        if tokio::time::timeout_at(deadline, tasks.join_next())
+            .await
+            .is_err()
+        {
+            for task_name in tasks.running() {
+                tracing::warn!(
+                    "{} was interrupted: it did not stop within the shutdown timeout",
+                    task_name
+                );
+            }
+            break;
+        }
+    }
     Ok(())
 }
+
+/// The long-running tasks of the process, tracked by name to report how they exit.
+#[derive(Default)]
+struct Tasks {
+    set: JoinSet<Result<(), anyhow::Error>>,
+    names: HashMap<tokio::task::Id, &'static str>,
+}
+
+impl Tasks {
+    fn spawn<E>(
+        &mut self,
+        task_name: &'static str,
+        task: impl Future<Output = Result<(), E>> + Send + 'static,
+    ) where
+        E: Into<anyhow::Error>,
+    {
+        let handle = self.set.spawn(async { task.await.map_err(Into::into) });
+        self.names.insert(handle.id(), task_name);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    fn running(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.names.values().copied()
+    }
+
+    /// Waits for the next task to exit, and reports it.
+    async fn join_next(&mut self) {
+        let (id, outcome) = match self.set.join_next_with_id().await {
+            Some(Ok((id, outcome))) => (id, Ok(outcome)),
+            Some(Err(e)) => (e.id(), Err(e)),
+            None => return std::future::pending().await,
+        };
+        let task_name = self.names.remove(&id).unwrap_or("A task");
+        report_exit(task_name, outcome);
+    }
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
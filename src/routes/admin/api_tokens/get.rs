@@ -0,0 +1,93 @@
+use crate::authentication::{list_api_tokens, ApiScope, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use html_escape::encode_text;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn api_tokens(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let tokens = list_api_tokens(&pool, **user_id).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for token in &tokens {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{scopes}</td>
+                <td>{created_at}</td>
+                <td>{last_used_at}</td>
+                <td>
+                    <form action="/admin/api-tokens/revoke" method="post">
+                        <input type="hidden" name="token_id" value="{token_id}">
+                        <button type="submit">Revoke</button>
+                    </form>
+                </td>
+            </tr>"#,
+            name = encode_text(&token.name),
+            scopes = encode_text(&token.scopes.join(", ")),
+            created_at = token.created_at.format("%Y-%m-%d %H:%M"),
+            last_used_at = token
+                .last_used_at
+                .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "Never".into()),
+            token_id = token.token_id,
+        )
+        .unwrap();
+    }
+    let mut scopes_html = String::new();
+    for scope in ApiScope::ALL {
+        writeln!(
+            scopes_html,
+            r#"<label><input type="checkbox" name="scope" value="{scope}"> {scope}</label><br>"#
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>API tokens</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <p>API tokens let scripts call the <code>/api/v1</code> endpoints on your behalf,
+                    with an <code>Authorization: Bearer &lt;token&gt;</code> header.</p>
+                    <table>
+                        <tr>
+                            <th>Name</th>
+                            <th>Scopes</th>
+                            <th>Created at (UTC)</th>
+                            <th>Last used at (UTC)</th>
+                            <th></th>
+                        </tr>
+                        {rows_html}
+                    </table>
+                    <h2>New token</h2>
+                    <form action="/admin/api-tokens" method="post">
+                        <label>Name
+                            <input type="text" placeholder="e.g. CI" name="name">
+                        </label>
+                        <br>
+                        {scopes_html}
+                        <button type="submit">Create token</button>
+                    </form>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+        )))
+}
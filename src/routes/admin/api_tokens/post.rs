@@ -0,0 +1,88 @@
+use crate::authentication::{ApiScope, Role, UserId};
+use crate::utils::{e400, e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use html_escape::encode_text;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The scope checkboxes share the same name, which a struct can't capture.
+type CreateFormData = Vec<(String, String)>;
+
+#[tracing::instrument(name = "Create an API token", skip(form, pool))]
+pub async fn create_api_token(
+    form: web::Form<CreateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (key, value) in form.0 {
+        match key.as_str() {
+            "name" => name = value.trim().to_owned(),
+            "scope" => scopes.push(ApiScope::try_from(value).map_err(e400)?),
+            _ => {}
+        }
+    }
+    if name.is_empty() {
+        FlashMessage::error("The token needs a name.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("The token needs at least one scope.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if let Some(scope) = scopes.iter().find(|s| s.required_role() > *role) {
+        FlashMessage::error(format!("The {} scope is not available to you.", scope)).send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    let token = crate::authentication::create_api_token(&pool, **user_id, &name, &scopes)
+        .await
+        .map_err(e500)?;
+    // Like recovery codes, the token is only ever shown here.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>New API token</title>
+                </head>
+                <body>
+                    <p>The {name} token has been created:</p>
+                    <p><code>{token}</code></p>
+                    <p>Store it somewhere safe: it won't be shown again.</p>
+                    <p><a href="/admin/api-tokens">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            name = encode_text(&name),
+            token = token.expose_secret(),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    token_id: Uuid,
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(form, pool))]
+pub async fn revoke_api_token(
+    form: web::Form<RevokeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked = crate::authentication::revoke_api_token(&pool, **user_id, form.0.token_id)
+        .await
+        .map_err(e500)?;
+    if !revoked {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The API token has been revoked.").send();
+    Ok(see_other("/admin/api-tokens"))
+}
@@ -1,14 +1,40 @@
-use crate::{authentication::UserId, utils::e500};
+use crate::authentication::{list_sessions, ActiveSession, Role, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
 use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
+use html_escape::encode_text;
 use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
 
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
+    session: TypedSession,
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+    flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    let role = role.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let username = encode_text(&username);
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = list_sessions(&pool, *user_id)
+        .await
+        .context("Failed to list the active sessions")
+        .map_err(e500)?;
+    let sessions_html = sessions_table(&sessions, current_session_id);
+    let owner_actions_html = if role == Role::Owner {
+        r#"<li><a href="/admin/users">Manage users</a></li>"#
+    } else {
+        ""
+    };
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -20,22 +46,68 @@ pub async fn admin_dashboard(
                     <title>Admin dashboard</title>
                 </head>
                 <body>
-                    <p>Welcome {username}!</p>
+                    {msg_html}
+                    <p>Welcome {username}! You are logged in as {role}.</p>
                     <p>Available actions:</p>
                     <ol>
                         <li><a href="/admin/password">Change password</a></li>
+                        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
+                        <li><a href="/admin/api-tokens">API tokens</a></li>
                         <li>
                             <form name="logoutForm" action="/admin/logout" method="post">
                                 <input type="submit" value="Logout">
                             </form>
                         <li><a href="/admin/newsletters">Send a newsletter</a></li>
+                        <li><a href="/admin/newsletters/drafts">Drafts</a></li>
+                        <li><a href="/admin/newsletters/issues">Past newsletters</a></li>
+                        <li><a href="/admin/newsletters/scheduled">Scheduled newsletters</a></li>
+                        <li><a href="/admin/newsletters/failures">Failed deliveries</a></li>
+                        {owner_actions_html}
                         </li>
                     </ol>
+                    <h2>Active sessions</h2>
+                    {sessions_html}
+                    <form action="/admin/sessions/revoke-others" method="post">
+                        <input type="submit" value="Log out all other sessions">
+                    </form>
                 </body>
             </html>"#
         )))
 }
 
+fn sessions_table(sessions: &[ActiveSession], current_session_id: Option<Uuid>) -> String {
+    let mut rows = String::new();
+    for s in sessions {
+        let action_html = if Some(s.session_id) == current_session_id {
+            "This session".to_owned()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/revoke" method="post">
+                    <input type="hidden" name="session_id" value="{}">
+                    <input type="submit" value="Log out">
+                </form>"#,
+                s.session_id
+            )
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            s.created_at.format("%Y-%m-%d %H:%M"),
+            s.last_seen_at.format("%Y-%m-%d %H:%M"),
+            encode_text(&s.ip_address),
+            encode_text(&s.user_agent),
+            action_html
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<table>
+            <tr><th>Logged in (UTC)</th><th>Last seen (UTC)</th><th>IP address</th><th>Browser</th><th></th></tr>
+            {rows}
+        </table>"#
+    )
+}
+
 #[tracing::instrument(name = "Get username", skip(pool))]
 pub async fn get_username(user_id: uuid::Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
     let row = sqlx::query!(
@@ -0,0 +1,111 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DeliveryFailure {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn delivery_failures(
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let failures = get_delivery_failures(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for failure in &failures {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/newsletters/issues/{issue_id}">{title}</a></td>
+                <td>{email}</td>
+                <td>{n_retries}</td>
+                <td>{failed_at}</td>
+                <td>{last_error}</td>
+                <td>
+                    <form action="/admin/newsletters/failures/requeue" method="post">
+                        <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                        <input hidden type="text" name="subscriber_email" value="{email_attribute}">
+                        <button type="submit">Requeue</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_text(&failure.title),
+            email = encode_text(&failure.subscriber_email),
+            n_retries = failure.n_retries,
+            failed_at = failure.failed_at.to_rfc3339(),
+            last_error = encode_text(&failure.last_error),
+            issue_id = failure.newsletter_issue_id,
+            email_attribute = encode_double_quoted_attribute(&failure.subscriber_email),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Delivery failures</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <p>{n_failures} deliveries gave up after too many retries.</p>
+                    <table>
+                        <tr>
+                            <th>Issue</th>
+                            <th>Subscriber</th>
+                            <th>Retries</th>
+                            <th>Failed at</th>
+                            <th>Last error</th>
+                            <th></th>
+                        </tr>
+                        {rows_html}
+                    </table>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            n_failures = failures.len(),
+        )))
+}
+
+#[tracing::instrument(name = "Get delivery failures", skip(pool))]
+async fn get_delivery_failures(pool: &PgPool) -> Result<Vec<DeliveryFailure>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_retries,
+            f.last_error,
+            f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        ORDER BY f.failed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve delivery failures.")?;
+    Ok(failures)
+}
@@ -0,0 +1,87 @@
+use crate::issue_delivery_worker::notify_workers;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Requeue a failed delivery",
+    skip(form, pool),
+    fields(
+        newsletter_issue_id = %form.newsletter_issue_id,
+        subscriber_email = %form.subscriber_email
+    )
+)]
+pub async fn requeue_delivery_failure(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let requeued = requeue(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    if requeued {
+        FlashMessage::info("The delivery has been requeued.").send();
+    } else {
+        FlashMessage::error("There is no such failed delivery.").send();
+    }
+    Ok(see_other("/admin/newsletters/failures"))
+}
+
+async fn requeue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_failures
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the delivery failure.")?
+    .rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+            )
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enqueue the delivery task.")?;
+    notify_workers(&mut transaction)
+        .await
+        .context("Failed to notify the delivery workers.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the requeued delivery.")?;
+    Ok(true)
+}
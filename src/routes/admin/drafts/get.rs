@@ -0,0 +1,207 @@
+use super::{get_draft, Draft};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn drafts(
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for draft in &drafts {
+        writeln!(
+            rows_html,
+            r#"<li><a href="/admin/newsletters/drafts/{}">{}</a></li>"#,
+            draft.newsletter_issue_id,
+            encode_text(&draft.title),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Drafts</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <p>{n_drafts} drafts are waiting to be published.</p>
+                    <ul>
+                        {rows_html}
+                    </ul>
+                    <p><a href="/admin/newsletters">Write a new newsletter</a></p>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            n_drafts = drafts.len(),
+        )))
+}
+
+pub async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, *draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    // Issues written before Markdown authoring keep their hand-written versions.
+    let content_fields = match &draft.markdown_content {
+        Some(markdown_content) => format!(
+            r#"<label>Newsletter content (Markdown)
+                            <textarea name="markdown_content" rows="20" cols="80">{}</textarea>
+                        </label>"#,
+            encode_text(markdown_content)
+        ),
+        None => format!(
+            r#"<label>Newsletter text content
+                            <textarea name="text_content" rows="20" cols="80">{}</textarea>
+                        </label>
+
+                        <label>Newsletter html content
+                            <textarea name="html_content" rows="20" cols="80">{}</textarea>
+                        </label>"#,
+            encode_text(&draft.text_content),
+            encode_text(&draft.html_content)
+        ),
+    };
+    let idempotency_key = Uuid::new_v4().to_string();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Edit draft</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <form action="/admin/newsletters/drafts/{draft_id}" method="post">
+                        <label>Newsletter title
+                            <input 
+                                type="text" 
+                                placeholder="title" 
+                                name="title"
+                                value="{title}"
+                            >
+                        </label>
+
+                        {content_fields}
+
+                        </br>
+                        <button type="submit">Save draft</button>
+                    </form>
+                    <p><a href="/admin/newsletters/drafts/{draft_id}/preview">Preview</a></p>
+                    <form action="/admin/newsletters/drafts/{draft_id}/test" method="post">
+                        <label>Send a test email to
+                            <input 
+                                type="email" 
+                                placeholder="you@example.com" 
+                                name="email"
+                            >
+                        </label>
+                        <button type="submit">Send test</button>
+                    </form>
+                    <form action="/admin/newsletters/drafts/{draft_id}/publish" method="post">
+                        <label>Send on (leave empty to send right away)
+                            <input 
+                                type="datetime-local" 
+                                name="scheduled_for"
+                            >
+                        </label>
+
+                        <label>Timezone
+                            <input 
+                                type="text" 
+                                placeholder="Europe/Paris" 
+                                name="timezone"
+                                value="UTC"
+                            >
+                        </label>
+
+                        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                        <button type="submit">Publish</button>
+                    </form>
+                    <p><a href="/admin/newsletters/drafts">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            draft_id = draft.newsletter_issue_id,
+            title = encode_double_quoted_attribute(&draft.title),
+        )))
+}
+
+pub async fn preview_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, *draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Preview - {title}</title>
+                </head>
+                <body>
+                    <h1>{title}</h1>
+                    <h2>HTML version</h2>
+                    <iframe sandbox srcdoc="{html_content}" width="800" height="600"></iframe>
+                    <h2>Plain text version</h2>
+                    <pre>{text_content}</pre>
+                    <p><a href="/admin/newsletters/drafts/{draft_id}">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            title = encode_text(&draft.title),
+            html_content = encode_double_quoted_attribute(&draft.html_content),
+            text_content = encode_text(&draft.text_content),
+            draft_id = draft.newsletter_issue_id,
+        )))
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, markdown_content
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY title
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve drafts.")?;
+    Ok(drafts)
+}
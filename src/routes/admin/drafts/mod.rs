@@ -0,0 +1,33 @@
+mod get;
+mod post;
+
+pub use get::{drafts, edit_draft_form, preview_draft};
+pub use post::{create_draft, save_draft, send_test_email};
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct Draft {
+    newsletter_issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+}
+
+#[tracing::instrument(name = "Get draft", skip(pool))]
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, markdown_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+}
@@ -0,0 +1,157 @@
+use super::get_draft;
+use crate::domain::{MergeTags, NewsletterContent, SubscriberEmail};
+use crate::email_client::EmailSender;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use html_escape::encode_text;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    markdown_content: Option<String>,
+    text_content: Option<String>,
+    html_content: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Create a draft", skip_all)]
+pub async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftFormData {
+        title,
+        markdown_content,
+        text_content,
+        html_content,
+    } = form.0;
+    let content =
+        NewsletterContent::parse(markdown_content, html_content, text_content).map_err(e400)?;
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            status
+            )
+        VALUES ($1, $2, $3, $4, $5, 'draft')
+        "#,
+        draft_id,
+        title,
+        content.text(),
+        content.html(),
+        content.markdown()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the draft.")
+    .map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(name = "Save a draft", skip(form, pool))]
+pub async fn save_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftFormData {
+        title,
+        markdown_content,
+        text_content,
+        html_content,
+    } = form.0;
+    let content =
+        NewsletterContent::parse(markdown_content, html_content, text_content).map_err(e400)?;
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            markdown_content = $5
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        *draft_id,
+        title,
+        content.text(),
+        content.html(),
+        content.markdown()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the draft.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(
+    name = "Send a test email for a draft",
+    skip(form, pool, email_client, base_url)
+)]
+pub async fn send_test_email(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<TestEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, *draft_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let edit_page = format!("/admin/newsletters/drafts/{}", draft.newsletter_issue_id);
+    let recipient = match SubscriberEmail::parse(form.0.email) {
+        Ok(recipient) => recipient,
+        Err(_) => {
+            FlashMessage::error("The test email address is not valid.").send();
+            return Ok(see_other(&edit_page));
+        }
+    };
+    let merge_tags = MergeTags::sample(recipient.as_ref(), &base_url.0);
+    email_client
+        .send_email(
+            &recipient,
+            &format!("[Test] {}", merge_tags.render_text(&draft.title)),
+            &merge_tags.render_html(&draft.html_content),
+            &merge_tags.render_text(&draft.text_content),
+            &[],
+        )
+        .await
+        .context("Failed to send the test email.")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "A test email has been sent to {}.",
+        encode_text(recipient.as_ref())
+    ))
+    .send();
+    Ok(see_other(&edit_page))
+}
@@ -0,0 +1,478 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use html_escape::encode_text;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub(crate) const ISSUES_PER_PAGE: i64 = 20;
+const DELIVERY_LOG_ENTRIES_PER_PAGE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct ListParameters {
+    page: Option<i64>,
+    order: Option<SortOrder>,
+}
+
+impl ListParameters {
+    /// Capped so that the page offset cannot overflow.
+    pub(crate) fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, i64::MAX / ISSUES_PER_PAGE)
+    }
+
+    pub(crate) fn order(&self) -> SortOrder {
+        self.order.unwrap_or(SortOrder::Desc)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeliveryLogParameters {
+    page: Option<i64>,
+}
+
+impl DeliveryLogParameters {
+    /// Capped so that the page offset cannot overflow.
+    fn page(&self) -> i64 {
+        self.page
+            .unwrap_or(1)
+            .clamp(1, i64::MAX / DELIVERY_LOG_ENTRIES_PER_PAGE)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    published_at: Option<DateTime<Utc>>,
+    scheduled_for: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryReport {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    queued: i64,
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+    failures: Vec<DeliveryLogEntry>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryLogEntry {
+    subscriber_email: String,
+    outcome: String,
+    message_id: Option<String>,
+    error: Option<String>,
+    n_attempts: i16,
+    enqueued_at: DateTime<Utc>,
+    completed_at: DateTime<Utc>,
+}
+
+pub async fn list_issues(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let page = parameters.page();
+    let order = parameters.order();
+    let (issues, n_issues) = get_issues(&pool, page, order).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        let edit_link = if issue.status == "draft" {
+            format!(
+                r#"<a href="/admin/newsletters/drafts/{}">Edit</a>"#,
+                issue.newsletter_issue_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/newsletters/issues/{issue_id}">{title}</a></td>
+                <td>{status}</td>
+                <td>{date}</td>
+                <td>{edit_link}</td>
+                <td>
+                    <form action="/admin/newsletters/issues/{issue_id}/clone" method="post">
+                        <button type="submit">Clone</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/newsletters/issues/{issue_id}/delete" method="post">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>"#,
+            issue_id = issue.newsletter_issue_id,
+            title = encode_text(&issue.title),
+            status = encode_text(&issue.status),
+            date = issue
+                .published_at
+                .or(issue.scheduled_for)
+                .map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+        )
+        .unwrap();
+    }
+    let n_pages = ((n_issues + ISSUES_PER_PAGE - 1) / ISSUES_PER_PAGE).max(1);
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/newsletters/issues?page={}&order={}">Previous</a> "#,
+            page - 1,
+            order.as_str()
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/newsletters/issues?page={}&order={}">Next</a>"#,
+            page + 1,
+            order.as_str()
+        )
+        .unwrap();
+    }
+    let toggle_order = match order {
+        SortOrder::Asc => SortOrder::Desc,
+        SortOrder::Desc => SortOrder::Asc,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Newsletter issues</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <p>{n_issues} newsletter issues.</p>
+                    <table>
+                        <tr>
+                            <th>Issue</th>
+                            <th>Status</th>
+                            <th>
+                                <a href="/admin/newsletters/issues?order={toggle_order}">Published at</a>
+                            </th>
+                            <th></th>
+                            <th></th>
+                            <th></th>
+                        </tr>
+                        {rows_html}
+                    </table>
+                    <p>{pagination_html}</p>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            toggle_order = toggle_order.as_str(),
+        )))
+}
+
+pub async fn issue_delivery_report(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match get_delivery_report(&pool, *issue_id).await.map_err(e500)? {
+        Some(report) => Ok(HttpResponse::Ok().json(report)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// The delivery log can hold a row per subscriber, so it is shown one page
+/// at a time; failures are listed in full above it.
+pub async fn issue_details(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<DeliveryLogParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let report = match get_delivery_report(&pool, *issue_id).await.map_err(e500)? {
+        Some(report) => report,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let page = parameters.page();
+    let log = get_delivery_log(&pool, *issue_id, page)
+        .await
+        .map_err(e500)?;
+    let mut failures_html = String::new();
+    for failure in &report.failures {
+        writeln!(
+            failures_html,
+            "<li>{} ({} attempts): {}</li>",
+            encode_text(&failure.subscriber_email),
+            failure.n_attempts,
+            encode_text(failure.error.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+    let mut log_html = String::new();
+    for entry in &log {
+        writeln!(
+            log_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{outcome}</td>
+                <td>{n_attempts}</td>
+                <td>{enqueued_at}</td>
+                <td>{completed_at}</td>
+                <td>{message_id}</td>
+                <td>{error}</td>
+            </tr>"#,
+            email = encode_text(&entry.subscriber_email),
+            outcome = entry.outcome,
+            n_attempts = entry.n_attempts,
+            enqueued_at = entry.enqueued_at.to_rfc3339(),
+            completed_at = entry.completed_at.to_rfc3339(),
+            message_id = encode_text(entry.message_id.as_deref().unwrap_or_default()),
+            error = encode_text(entry.error.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+    let n_entries = report.sent + report.failed + report.skipped;
+    let n_pages =
+        ((n_entries + DELIVERY_LOG_ENTRIES_PER_PAGE - 1) / DELIVERY_LOG_ENTRIES_PER_PAGE).max(1);
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/newsletters/issues/{}?page={}">Previous</a> "#,
+            report.newsletter_issue_id,
+            page - 1
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/newsletters/issues/{}?page={}">Next</a>"#,
+            report.newsletter_issue_id,
+            page + 1
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>{title}</title>
+                </head>
+                <body>
+                    <h1>{title}</h1>
+                    <p>Status: {status}</p>
+                    <ul>
+                        <li>Queued: {queued}</li>
+                        <li>Sent: {sent}</li>
+                        <li>Failed: {failed}</li>
+                        <li>Skipped: {skipped}</li>
+                    </ul>
+                    <h2>Failures</h2>
+                    <ul>
+                        {failures_html}
+                    </ul>
+                    <h2>Delivery log</h2>
+                    <table>
+                        <tr>
+                            <th>Subscriber</th>
+                            <th>Outcome</th>
+                            <th>Attempts</th>
+                            <th>Enqueued at</th>
+                            <th>Completed at</th>
+                            <th>Message id</th>
+                            <th>Error</th>
+                        </tr>
+                        {log_html}
+                    </table>
+                    <p>{pagination_html}</p>
+                    <p><a href="/admin/newsletters/issues/{issue_id}/delivery">Full report (JSON)</a></p>
+                    <p><a href="/admin/newsletters/issues">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            title = encode_text(&report.title),
+            status = encode_text(&report.status),
+            queued = report.queued,
+            sent = report.sent,
+            failed = report.failed,
+            skipped = report.skipped,
+            issue_id = report.newsletter_issue_id,
+        )))
+}
+
+/// Issues that were never published (drafts, scheduled issues) come last,
+/// whatever the sort order.
+#[tracing::instrument(name = "Get newsletter issues", skip(pool, order))]
+pub(crate) async fn get_issues(
+    pool: &PgPool,
+    page: i64,
+    order: SortOrder,
+) -> Result<(Vec<IssueSummary>, i64), anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, status, published_at, scheduled_for
+        FROM newsletter_issues
+        ORDER BY
+            CASE WHEN $1 THEN published_at END ASC NULLS LAST,
+            CASE WHEN NOT $1 THEN published_at END DESC NULLS LAST,
+            scheduled_for ASC,
+            title
+        LIMIT $2
+        OFFSET $3
+        "#,
+        order == SortOrder::Asc,
+        ISSUES_PER_PAGE,
+        (page - 1) * ISSUES_PER_PAGE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve newsletter issues.")?;
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(pool)
+        .await
+        .context("Failed to count newsletter issues.")?
+        .count;
+    Ok((issues, n_issues))
+}
+
+#[tracing::instrument(name = "Get delivery report", skip(pool))]
+async fn get_delivery_report(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<DeliveryReport>, anyhow::Error> {
+    let report = sqlx::query!(
+        r#"
+        SELECT
+            title,
+            status,
+            (
+                SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) as "queued!",
+            (
+                SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome = 'sent'
+            ) as "sent!",
+            (
+                SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome = 'failed'
+            ) as "failed!",
+            (
+                SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome LIKE 'skipped%'
+            ) as "skipped!"
+        FROM newsletter_issues i
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the delivery counts.")?;
+    let report = match report {
+        Some(report) => report,
+        None => return Ok(None),
+    };
+    let failures = sqlx::query_as!(
+        DeliveryLogEntry,
+        r#"
+        SELECT
+            subscriber_email,
+            outcome,
+            message_id,
+            error,
+            n_attempts,
+            enqueued_at,
+            completed_at
+        FROM issue_delivery_log
+        WHERE
+            newsletter_issue_id = $1 AND
+            outcome = 'failed'
+        ORDER BY completed_at
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery failures.")?;
+    Ok(Some(DeliveryReport {
+        newsletter_issue_id: issue_id,
+        title: report.title,
+        status: report.status,
+        queued: report.queued,
+        sent: report.sent,
+        failed: report.failed,
+        skipped: report.skipped,
+        failures,
+    }))
+}
+
+#[tracing::instrument(name = "Get delivery log", skip(pool))]
+async fn get_delivery_log(
+    pool: &PgPool,
+    issue_id: Uuid,
+    page: i64,
+) -> Result<Vec<DeliveryLogEntry>, anyhow::Error> {
+    let log = sqlx::query_as!(
+        DeliveryLogEntry,
+        r#"
+        SELECT
+            subscriber_email,
+            outcome,
+            message_id,
+            error,
+            n_attempts,
+            enqueued_at,
+            completed_at
+        FROM issue_delivery_log
+        WHERE newsletter_issue_id = $1
+        ORDER BY completed_at, subscriber_email
+        LIMIT $2
+        OFFSET $3
+        "#,
+        issue_id,
+        DELIVERY_LOG_ENTRIES_PER_PAGE,
+        (page - 1) * DELIVERY_LOG_ENTRIES_PER_PAGE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery log.")?;
+    Ok(log)
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub(crate) use get::{get_issues, ISSUES_PER_PAGE};
+pub use get::{issue_delivery_report, issue_details, list_issues, ListParameters};
+pub use post::{clone_issue, delete_issue};
@@ -0,0 +1,141 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Clone a newsletter issue", skip(pool))]
+pub async fn clone_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = Uuid::new_v4();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            status
+        )
+        SELECT $2, title, text_content, html_content, markdown_content, 'draft'
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        *issue_id,
+        draft_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to clone the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The newsletter issue has been cloned into a new draft.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+#[tracing::instrument(name = "Delete a newsletter issue", skip(pool))]
+pub async fn delete_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Lock the issue so that the scheduler can't enqueue it while we check.
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        *issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the newsletter issue.")
+    .map_err(e500)?;
+    if issue.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let n_pending_deliveries = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        *issue_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to count pending deliveries.")
+    .map_err(e500)?
+    .count;
+    if n_pending_deliveries > 0 {
+        FlashMessage::error(format!(
+            "The newsletter issue can't be deleted while {} deliveries are still pending.",
+            n_pending_deliveries
+        ))
+        .send();
+        return Ok(see_other("/admin/newsletters/issues"));
+    }
+    // Failed deliveries wait for an admin to requeue them: they are never
+    // dropped along with the issue.
+    let n_failed_deliveries = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM issue_delivery_failures
+        WHERE newsletter_issue_id = $1
+        "#,
+        *issue_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to count failed deliveries.")
+    .map_err(e500)?
+    .count;
+    if n_failed_deliveries > 0 {
+        FlashMessage::error(format!(
+            "The newsletter issue can't be deleted while {} failed deliveries \
+            are listed on the failures page.",
+            n_failed_deliveries
+        ))
+        .send();
+        return Ok(see_other("/admin/newsletters/issues"));
+    }
+    for query in [
+        sqlx::query!(
+            "DELETE FROM issue_delivery_log WHERE newsletter_issue_id = $1",
+            *issue_id
+        ),
+        sqlx::query!(
+            "DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1",
+            *issue_id
+        ),
+    ] {
+        query
+            .execute(&mut transaction)
+            .await
+            .context("Failed to delete the newsletter issue.")
+            .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the deletion of the newsletter issue.")
+        .map_err(e500)?;
+    FlashMessage::info("The newsletter issue and its delivery log have been deleted.").send();
+    Ok(see_other("/admin/newsletters/issues"))
+}
@@ -1,9 +1,36 @@
+mod api_tokens;
 mod dashboard;
+mod delivery_failures;
+mod drafts;
+mod issues;
 mod logout;
 mod newsletters;
 mod password;
+mod scheduled_issues;
+mod sessions;
+mod two_factor;
+mod users;
 
+pub use api_tokens::{api_tokens, create_api_token, revoke_api_token};
 pub use dashboard::admin_dashboard;
+pub use delivery_failures::{delivery_failures, requeue_delivery_failure};
+pub use drafts::{
+    create_draft, drafts, edit_draft_form, preview_draft, save_draft, send_test_email,
+};
+pub use issues::{
+    clone_issue, delete_issue, issue_delivery_report, issue_details, list_issues, ListParameters,
+};
+pub(crate) use issues::{get_issues, ISSUES_PER_PAGE};
 pub use logout::log_out;
-pub use newsletters::{publish_newsletter, submit_newsletter_form};
+pub(crate) use newsletters::{insert_issue, parse_schedule};
+pub use newsletters::{publish_draft, publish_newsletter, submit_newsletter_form};
 pub use password::{change_password, change_password_form};
+pub use scheduled_issues::{cancel_scheduled_issue, reschedule_issue, scheduled_issues};
+pub use sessions::{revoke_other_sessions, revoke_session};
+pub use two_factor::{
+    confirm_two_factor, disable_two_factor, set_up_two_factor, two_factor_settings,
+};
+pub use users::{
+    change_user_email, change_user_role, create_user, disable_user, enable_user,
+    require_two_factor, users,
+};
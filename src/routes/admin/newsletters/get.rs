@@ -32,26 +32,41 @@ pub async fn submit_newsletter_form(
                             >
                         </label>
 
-                        <label>Newsletter text content
+                        <p>Personalize your newsletter with <code>{{{{ name }}}}</code>,
+                        <code>{{{{ email }}}}</code>, <code>{{{{ subscribed_at }}}}</code>
+                        and <code>{{{{ unsubscribe_url }}}}</code>.</p>
+                        <label>Newsletter content (Markdown)
+                            <textarea 
+                                placeholder="Write your newsletter in Markdown" 
+                                name="markdown_content"
+                                rows="20"
+                                cols="80"
+                            ></textarea>
+                        </label>
+
+                        </br>
+                        <label>Send on (leave empty to send right away)
                             <input 
-                                type="text" 
-                                placeholder="text content" 
-                                name="text_content"
+                                type="datetime-local" 
+                                name="scheduled_for"
                             >
                         </label>
 
-                        <label>Newsletter html content
+                        <label>Timezone
                             <input 
                                 type="text" 
-                                placeholder="html content" 
-                                name="html_content"
+                                placeholder="Europe/Paris" 
+                                name="timezone"
+                                value="UTC"
                             >
                         </label>
 
                         </br>
-                        <input hidden type="text" name="idempotency_key" value="{idempotency_key}"
+                        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
                         <button type="submit">Submit newsletter</button>
+                        <button type="submit" formaction="/admin/newsletters/drafts">Save as draft</button>
                     </form>
+                    <p><a href="/admin/newsletters/drafts">Drafts</a></p>
                     <p><a href="/admin/dashboard">&lt; - Back</a></p>
                 </body>
             </html>
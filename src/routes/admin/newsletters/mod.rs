@@ -2,4 +2,5 @@ mod get;
 mod post;
 
 pub use get::submit_newsletter_form;
-pub use post::publish_newsletter;
+pub(crate) use post::{insert_issue, parse_schedule};
+pub use post::{publish_draft, publish_newsletter};
@@ -1,19 +1,38 @@
 use crate::authentication::UserId;
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::configuration::IdempotencySettings;
+use crate::domain::{IssueSlug, MergeTags, NewsletterContent, Schedule};
+use crate::idempotency::{
+    in_progress_response, payload_mismatch_response, save_response, try_processing, IdempotencyKey,
+    NextAction, RequestFingerprint,
+};
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
 use crate::utils::{e400, e500, see_other};
-use actix_web::web::{Data, Form, ReqData};
+use actix_web::web::{Data, Form, Path, ReqData};
 use actix_web::HttpResponse;
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::Utc;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct FormData {
     title: String,
-    text_content: String,
-    html_content: String,
+    markdown_content: Option<String>,
+    text_content: Option<String>,
+    html_content: Option<String>,
+    #[serde(skip_serializing)]
     idempotency_key: String,
+    scheduled_for: Option<String>,
+    timezone: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct PublishDraftFormData {
+    #[serde(skip_serializing)]
+    idempotency_key: String,
+    scheduled_for: Option<String>,
+    timezone: Option<String>,
 }
 
 #[tracing::instrument(
@@ -24,57 +43,202 @@ pub struct FormData {
 pub async fn publish_newsletter(
     form: Form<FormData>,
     pool: Data<PgPool>,
+    idempotency: Data<IdempotencySettings>,
     user_id: ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    let fingerprint = RequestFingerprint::of(&form.0).map_err(e500)?;
     let FormData {
         title,
+        markdown_content,
         text_content,
         html_content,
         idempotency_key,
+        scheduled_for,
+        timezone,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
+    let content =
+        NewsletterContent::parse(markdown_content, html_content, text_content).map_err(e400)?;
+    MergeTags::validate(&title)
+        .and_then(|_| content.validate_merge_tags())
+        .map_err(e400)?;
+    let schedule = parse_schedule(scheduled_for, timezone).map_err(e400)?;
+    let mut transaction = match try_processing(
+        &pool,
+        &idempotency_key,
+        &fingerprint,
+        *user_id,
+        &idempotency,
+    )
+    .await
+    .map_err(e500)?
     {
         NextAction::StartProcessing(t) => t,
         NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
+            success_message(schedule.as_ref()).send();
             return Ok(saved_response);
         }
+        NextAction::InProgress => return Ok(in_progress_response(&idempotency)),
+        NextAction::PayloadMismatch => return Ok(payload_mismatch_response()),
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    insert_issue(&mut transaction, &title, &content, schedule.as_ref())
         .await
-        .context("Failed to store newsletter issue details")
         .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let response = see_other("/admin/newsletters");
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
-        .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
+    success_message(schedule.as_ref()).send();
+    Ok(response)
+}
+
+#[tracing::instrument(
+    name = "Publish a draft",
+    skip(form, pool, idempotency, user_id),
+    fields(user_id=%&*user_id)
+    )]
+pub async fn publish_draft(
+    draft_id: Path<Uuid>,
+    form: Form<PublishDraftFormData>,
+    pool: Data<PgPool>,
+    idempotency: Data<IdempotencySettings>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let fingerprint = RequestFingerprint::of(&(*draft_id, &form.0)).map_err(e500)?;
+    let PublishDraftFormData {
+        idempotency_key,
+        scheduled_for,
+        timezone,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let schedule = parse_schedule(scheduled_for, timezone).map_err(e400)?;
+    let title = match get_draft_content(&pool, *draft_id)
+        .await
+        .context("Failed to retrieve the draft")
+        .map_err(e500)?
+    {
+        Some((title, content)) => {
+            MergeTags::validate(&title)
+                .and_then(|_| content.validate_merge_tags())
+                .map_err(e400)?;
+            title
+        }
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let mut transaction = match try_processing(
+        &pool,
+        &idempotency_key,
+        &fingerprint,
+        *user_id,
+        &idempotency,
+    )
+    .await
+    .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(schedule.as_ref()).send();
+            return Ok(saved_response);
+        }
+        NextAction::InProgress => return Ok(in_progress_response(&idempotency)),
+        NextAction::PayloadMismatch => return Ok(payload_mismatch_response()),
+    };
+    let n_updated_rows = match &schedule {
+        Some(schedule) => schedule_draft(&mut transaction, *draft_id, schedule)
+            .await
+            .context("Failed to schedule the draft")
+            .map_err(e500)?,
+        None => {
+            let slug = IssueSlug::new(&title, *draft_id);
+            let n_updated_rows = publish_draft_now(&mut transaction, *draft_id, &slug)
+                .await
+                .context("Failed to publish the draft")
+                .map_err(e500)?;
+            if n_updated_rows > 0 {
+                enqueue_delivery_tasks(&mut transaction, *draft_id)
+                    .await
+                    .context("Failed to enqueue delivery tasks")
+                    .map_err(e500)?;
+            }
+            n_updated_rows
+        }
+    };
+    if n_updated_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
     let response = see_other("/admin/newsletters");
     let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
         .map_err(e500)?;
-    success_message().send();
+    success_message(schedule.as_ref()).send();
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info(
-        "The newsletter issue has been accepted \
-        - emails will go out shortly.",
-    )
+/// Stores a new issue, and queues it for delivery unless it is scheduled.
+pub(crate) async fn insert_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    content: &NewsletterContent,
+    schedule: Option<&Schedule>,
+) -> Result<Uuid, anyhow::Error> {
+    match schedule {
+        Some(schedule) => insert_scheduled_newsletter_issue(transaction, title, content, schedule)
+            .await
+            .context("Failed to store scheduled newsletter issue details"),
+        None => {
+            let issue_id = insert_newsletter_issue(transaction, title, content)
+                .await
+                .context("Failed to store newsletter issue details")?;
+            enqueue_delivery_tasks(transaction, issue_id)
+                .await
+                .context("Failed to enqueue delivery tasks")?;
+            Ok(issue_id)
+        }
+    }
+}
+
+/// An empty send time means the issue goes out right away.
+pub(crate) fn parse_schedule(
+    scheduled_for: Option<String>,
+    timezone: Option<String>,
+) -> Result<Option<Schedule>, String> {
+    match scheduled_for.filter(|s| !s.is_empty()) {
+        Some(scheduled_for) => {
+            let timezone = timezone.unwrap_or_else(|| "UTC".into());
+            let schedule = Schedule::parse(&scheduled_for, &timezone)?;
+            if schedule.send_at() <= Utc::now() {
+                return Err("The scheduled time must be in the future.".into());
+            }
+            Ok(Some(schedule))
+        }
+        None => Ok(None),
+    }
+}
+
+fn success_message(schedule: Option<&Schedule>) -> FlashMessage {
+    match schedule {
+        Some(schedule) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled \
+            - emails will go out on {} UTC.",
+            schedule.send_at().format("%Y-%m-%d %H:%M")
+        )),
+        None => FlashMessage::info(
+            "The newsletter issue has been accepted \
+            - emails will go out shortly.",
+        ),
+    }
 }
 
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &NewsletterContent,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let slug = IssueSlug::new(title, newsletter_issue_id);
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
@@ -82,14 +246,19 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            markdown_content,
+            published_at,
+            status,
+            slug
             )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, now(), 'published', $6)
         "#,
         newsletter_issue_id,
         title,
-        text_content,
-        html_content
+        content.text(),
+        content.html(),
+        content.markdown(),
+        slug.as_ref()
     )
     .execute(transaction)
     .await?;
@@ -97,22 +266,111 @@ async fn insert_newsletter_issue(
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+async fn insert_scheduled_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
+    title: &str,
+    content: &NewsletterContent,
+    schedule: &Schedule,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO issue_delivery_queue(
+        INSERT INTO newsletter_issues (
             newsletter_issue_id,
-            subscriber_email)
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            scheduled_for,
+            scheduled_timezone,
+            status
+            )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 'scheduled')
         "#,
         newsletter_issue_id,
+        title,
+        content.text(),
+        content.html(),
+        content.markdown(),
+        schedule.send_at(),
+        schedule.timezone()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_draft_content(
+    pool: &PgPool,
+    draft_id: Uuid,
+) -> Result<Option<(String, NewsletterContent)>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content, markdown_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r.map(|r| {
+        let content =
+            NewsletterContent::from_stored(r.markdown_content, r.html_content, r.text_content);
+        (r.title, content)
+    }))
+}
+
+#[tracing::instrument(skip_all)]
+async fn publish_draft_now(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    slug: &IssueSlug,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now(),
+            slug = $2
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        draft_id,
+        slug.as_ref()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    schedule: &Schedule,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'scheduled',
+            scheduled_for = $2,
+            scheduled_timezone = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        draft_id,
+        schedule.send_at(),
+        schedule.timezone()
     )
     .execute(transaction)
     .await?;
-    Ok(())
+    Ok(result.rows_affected())
 }
@@ -6,6 +6,7 @@ use std::fmt::Write;
 pub async fn change_password_form(
     flash_message: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
     let mut msg_html = String::new();
     for m in flash_message.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
@@ -23,6 +24,7 @@ pub async fn change_password_form(
                 <body>
                     {msg_html}
                     <form action="/admin/password" method="post">
+                        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
                         <label>Current password
                             <input 
                                 type="password" 
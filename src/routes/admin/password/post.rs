@@ -1,8 +1,12 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials, UserId};
+use crate::authentication::{
+    revoke_other_sessions, validate_credentials, AuthError, Credentials, UserId,
+};
 use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::ExposeSecret;
 use secrecy::Secret;
 use sqlx::PgPool;
@@ -17,6 +21,7 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    session: TypedSession,
     user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
@@ -40,8 +45,25 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    let mut transaction = pool
+        .begin()
         .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    crate::authentication::change_password(*user_id, form.0.new_password, &mut transaction)
+        .await
+        .map_err(e500)?;
+    // Whoever knew the old password must not keep their foot in the door.
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_other_sessions(&mut transaction, *user_id, session_id)
+            .await
+            .context("Failed to revoke the other sessions")
+            .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password change")
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
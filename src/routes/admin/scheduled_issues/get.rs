@@ -0,0 +1,111 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    scheduled_for: DateTime<Utc>,
+    scheduled_timezone: String,
+}
+
+pub async fn scheduled_issues(
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        let timezone: Tz = issue.scheduled_timezone.parse().unwrap_or(Tz::UTC);
+        let local_time = issue.scheduled_for.with_timezone(&timezone);
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{local_time} ({timezone})</td>
+                <td>
+                    <form action="/admin/newsletters/scheduled/reschedule" method="post">
+                        <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                        <input type="datetime-local" name="scheduled_for" value="{local_input}">
+                        <input type="text" name="timezone" value="{timezone_attribute}">
+                        <button type="submit">Reschedule</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/newsletters/scheduled/cancel" method="post">
+                        <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                        <button type="submit">Cancel</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_text(&issue.title),
+            local_time = local_time.format("%Y-%m-%d %H:%M"),
+            timezone = encode_text(timezone.name()),
+            issue_id = issue.newsletter_issue_id,
+            local_input = local_time.format("%Y-%m-%dT%H:%M"),
+            timezone_attribute = encode_double_quoted_attribute(timezone.name()),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Scheduled issues</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <p>{n_issues} issues are scheduled.</p>
+                    <table>
+                        <tr>
+                            <th>Issue</th>
+                            <th>Send time</th>
+                            <th></th>
+                            <th></th>
+                        </tr>
+                        {rows_html}
+                    </table>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            n_issues = issues.len(),
+        )))
+}
+
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            scheduled_for as "scheduled_for!",
+            scheduled_timezone as "scheduled_timezone!"
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY scheduled_for
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve scheduled issues.")?;
+    Ok(issues)
+}
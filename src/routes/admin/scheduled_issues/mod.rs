@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::scheduled_issues;
+pub use post::{cancel_scheduled_issue, reschedule_issue};
@@ -0,0 +1,92 @@
+use crate::domain::Schedule;
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    newsletter_issue_id: Uuid,
+    scheduled_for: String,
+    timezone: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CancelFormData {
+    newsletter_issue_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(form, pool),
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn reschedule_issue(
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let schedule = Schedule::parse(&form.scheduled_for, &form.timezone).map_err(e400)?;
+    if schedule.send_at() <= Utc::now() {
+        return Err(e400("The scheduled time must be in the future."));
+    }
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            scheduled_for = $2,
+            scheduled_timezone = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled'
+        "#,
+        form.newsletter_issue_id,
+        schedule.send_at(),
+        schedule.timezone()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows > 0 {
+        FlashMessage::info("The newsletter issue has been rescheduled.").send();
+    } else {
+        FlashMessage::error("The newsletter issue is no longer scheduled.").send();
+    }
+    Ok(see_other("/admin/newsletters/scheduled"))
+}
+
+#[tracing::instrument(
+    name = "Cancel a scheduled newsletter issue",
+    skip(form, pool),
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn cancel_scheduled_issue(
+    form: web::Form<CancelFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled'
+        "#,
+        form.newsletter_issue_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to cancel the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows > 0 {
+        FlashMessage::info("The newsletter issue has been cancelled.").send();
+    } else {
+        FlashMessage::error("The newsletter issue is no longer scheduled.").send();
+    }
+    Ok(see_other("/admin/newsletters/scheduled"))
+}
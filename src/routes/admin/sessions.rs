@@ -0,0 +1,50 @@
+use crate::authentication::UserId;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(skip(form, pool, user_id), fields(user_id=%&*user_id))]
+pub async fn revoke_session(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let revoked = crate::authentication::revoke_session(&pool, *user_id, form.0.session_id)
+        .await
+        .context("Failed to revoke the session")
+        .map_err(e500)?;
+    if !revoked {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The session has been logged out.").send();
+    Ok(see_other("/admin/dashboard"))
+}
+
+#[tracing::instrument(skip(pool, session, user_id), fields(user_id=%&*user_id))]
+pub async fn revoke_other_sessions(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let session_id = session
+        .get_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session is not tracked"))?;
+    crate::authentication::revoke_other_sessions(pool.get_ref(), *user_id, session_id)
+        .await
+        .context("Failed to revoke the other sessions")
+        .map_err(e500)?;
+    FlashMessage::info("All your other sessions have been logged out.").send();
+    Ok(see_other("/admin/dashboard"))
+}
@@ -0,0 +1,99 @@
+use crate::authentication::{
+    get_two_factor_status, is_two_factor_required, TotpSecret, TwoFactorStatus, UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use html_escape::encode_text;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn two_factor_settings(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let required_html = if is_two_factor_required(&pool).await.map_err(e500)? {
+        "<p>Two-factor authentication is required for all users.</p>"
+    } else {
+        ""
+    };
+    let status_html = match get_two_factor_status(&pool, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        TwoFactorStatus::Disabled => r#"
+            <p>Two-factor authentication is disabled.</p>
+            <p>Once it is enabled, you will need a code from an authenticator app on top of your password to log in.</p>
+            <form action="/admin/two-factor/setup" method="post">
+                <button type="submit">Set up two-factor authentication</button>
+            </form>"#
+            .to_string(),
+        TwoFactorStatus::Pending(secret) => {
+            let username = get_username(*user_id, &pool).await.map_err(e500)?;
+            setup_html(&secret, &username)?
+        }
+        TwoFactorStatus::Enabled => r#"
+            <p>Two-factor authentication is enabled.</p>
+            <form action="/admin/two-factor/disable" method="post">
+                <label>Enter a code to disable it
+                    <input type="text" autocomplete="one-time-code" name="code">
+                </label>
+                <button type="submit">Disable two-factor authentication</button>
+            </form>"#
+            .to_string(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Two-factor authentication</title>
+                </head>
+                <body>
+                    {msg_html}
+                    {required_html}
+                    {status_html}
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+        )))
+}
+
+fn setup_html(secret: &TotpSecret, username: &str) -> Result<String, actix_web::Error> {
+    let provisioning_uri = secret.provisioning_uri(username);
+    let qr_code = QrCode::new(provisioning_uri.as_bytes())
+        .map_err(e500)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(format!(
+        r#"
+            <p>Scan this QR code with your authenticator app:</p>
+            {qr_code}
+            <p>Or enter this key by hand: <code>{secret}</code></p>
+            <form action="/admin/two-factor/confirm" method="post">
+                <label>Then enter the code it shows
+                    <input type="text" autocomplete="one-time-code" name="code">
+                </label>
+                <button type="submit">Enable two-factor authentication</button>
+            </form>
+            <form action="/admin/two-factor/setup" method="post">
+                <button type="submit">Start over with a new key</button>
+            </form>"#,
+        secret = encode_text(&secret.to_base32()),
+    ))
+}
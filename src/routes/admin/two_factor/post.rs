@@ -0,0 +1,98 @@
+use crate::authentication::{
+    confirm_two_factor_setup, is_two_factor_required, start_two_factor_setup, verify_second_factor,
+    UserId,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use html_escape::encode_text;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(name = "Set up two-factor authentication", skip(pool))]
+pub async fn set_up_two_factor(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    start_two_factor_setup(&pool, **user_id)
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/two-factor"))
+}
+
+#[tracing::instrument(name = "Confirm two-factor authentication", skip(form, pool))]
+pub async fn confirm_two_factor(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recovery_codes =
+        match confirm_two_factor_setup(&pool, **user_id, form.0.code.expose_secret())
+            .await
+            .map_err(e500)?
+        {
+            Some(recovery_codes) => recovery_codes,
+            None => {
+                FlashMessage::error("The code is not valid.").send();
+                return Ok(see_other("/admin/two-factor"));
+            }
+        };
+    // The recovery codes are only ever shown here: we just keep their hashes.
+    let mut codes_html = String::new();
+    for code in &recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", encode_text(code)).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Recovery codes</title>
+                </head>
+                <body>
+                    <p>Two-factor authentication is enabled.</p>
+                    <p>If you lose access to your authenticator app, you can log in with one of these recovery codes instead.
+                    Each of them works once. Store them somewhere safe: they won't be shown again.</p>
+                    <ul>
+                        {codes_html}
+                    </ul>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+        )))
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(form, pool))]
+pub async fn disable_two_factor(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if is_two_factor_required(&pool).await.map_err(e500)? {
+        FlashMessage::error("Two-factor authentication is required for all users.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    if !verify_second_factor(&pool, **user_id, form.0.code.expose_secret())
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The code is not valid.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    crate::authentication::disable_two_factor(&pool, **user_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/two-factor"))
+}
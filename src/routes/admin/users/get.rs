@@ -0,0 +1,205 @@
+use crate::authentication::{is_two_factor_required, Role, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    disabled: bool,
+    two_factor_enabled: bool,
+}
+
+pub async fn users(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_user_id = *user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let users = get_users(&pool).await.map_err(e500)?;
+    let two_factor_html = if is_two_factor_required(&pool).await.map_err(e500)? {
+        r#"<p>Two-factor authentication is required for all users.</p>
+        <input hidden type="text" name="required" value="false">
+        <button type="submit">Make it optional</button>"#
+    } else {
+        r#"<p>Two-factor authentication is optional.</p>
+        <input hidden type="text" name="required" value="true">
+        <button type="submit">Require it for all users</button>"#
+    };
+    let mut rows_html = String::new();
+    for user in &users {
+        let actions_html = if user.user_id == current_user_id {
+            "<td>(you)</td><td></td>".to_string()
+        } else {
+            let (toggle_action, toggle_label) = if user.disabled {
+                ("enable", "Enable")
+            } else {
+                ("disable", "Disable")
+            };
+            format!(
+                r#"<td>
+                    <form action="/admin/users/{user_id}/role" method="post">
+                        {role_key}
+                        {role_select}
+                        <button type="submit">Change role</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/users/{user_id}/{toggle_action}" method="post">
+                        {toggle_key}
+                        <button type="submit">{toggle_label}</button>
+                    </form>
+                </td>"#,
+                user_id = user.user_id,
+                role_key = idempotency_key_input(),
+                role_select = role_select(Some(&user.role)),
+                toggle_key = idempotency_key_input(),
+            )
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{username}</td>
+                <td>
+                    <form action="/admin/users/{user_id}/email" method="post">
+                        {email_key}
+                        <input type="email" name="email" value="{email}">
+                        <button type="submit">Change email</button>
+                    </form>
+                </td>
+                <td>{role}</td>
+                <td>{status}</td>
+                <td>{two_factor}</td>
+                {actions_html}
+            </tr>"#,
+            username = encode_text(&user.username),
+            user_id = user.user_id,
+            email_key = idempotency_key_input(),
+            email = encode_double_quoted_attribute(user.email.as_deref().unwrap_or_default()),
+            role = user.role,
+            status = if user.disabled { "disabled" } else { "active" },
+            two_factor = if user.two_factor_enabled { "on" } else { "off" },
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Users</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <table>
+                        <tr>
+                            <th>Username</th>
+                            <th>Email</th>
+                            <th>Role</th>
+                            <th>Status</th>
+                            <th>Two-factor</th>
+                            <th></th>
+                            <th></th>
+                        </tr>
+                        {rows_html}
+                    </table>
+                    <form action="/admin/users/two-factor" method="post">
+                        {two_factor_key}
+                        {two_factor_html}
+                    </form>
+                    <h2>Add a user</h2>
+                    <form action="/admin/users" method="post">
+                        {create_user_key}
+                        <label>Username
+                            <input type="text" placeholder="Enter username" name="username">
+                        </label>
+                        <br>
+                        <label>Email
+                            <input type="email" placeholder="Enter email address" name="email">
+                        </label>
+                        <br>
+                        <label>Password
+                            <input type="password" placeholder="Enter password" name="password">
+                        </label>
+                        <br>
+                        <label>Confirm password
+                            <input type="password" placeholder="Type the password again" name="password_check">
+                        </label>
+                        <br>
+                        <label>Role
+                            {role_select}
+                        </label>
+                        <br>
+                        <button type="submit">Add user</button>
+                    </form>
+                    <p><a href="/admin/dashboard">&lt; - Back</a></p>
+                </body>
+            </html>
+        "#,
+            two_factor_key = idempotency_key_input(),
+            create_user_key = idempotency_key_input(),
+            role_select = role_select(None),
+        )))
+}
+
+/// Each form gets its own key: reusing one for another request is an error.
+fn idempotency_key_input() -> String {
+    format!(
+        r#"<input hidden type="text" name="idempotency_key" value="{}">"#,
+        Uuid::new_v4()
+    )
+}
+
+fn role_select(selected: Option<&str>) -> String {
+    let mut options_html = String::new();
+    for role in Role::ALL {
+        let selected = if selected == Some(role.as_str()) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            options_html,
+            r#"<option value="{role}"{selected}>{role}</option>"#
+        )
+        .unwrap();
+    }
+    format!(r#"<select name="role">{options_html}</select>"#)
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT
+            user_id,
+            username,
+            email,
+            role,
+            disabled,
+            totp_confirmed_at IS NOT NULL as "two_factor_enabled!"
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve users.")?;
+    Ok(users)
+}
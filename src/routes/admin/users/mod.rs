@@ -0,0 +1,7 @@
+mod get;
+mod post;
+
+pub use get::users;
+pub use post::{
+    change_user_email, change_user_role, create_user, disable_user, enable_user, require_two_factor,
+};
@@ -0,0 +1,224 @@
+use crate::authentication::{set_two_factor_required, Role, UserId};
+use crate::domain::SubscriberEmail;
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use html_escape::encode_text;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct CreateUserFormData {
+    username: String,
+    email: Option<String>,
+    password: Secret<String>,
+    password_check: Secret<String>,
+    role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    required: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Create a user",
+    skip(form, pool),
+    fields(username = %form.username, role = %form.role)
+)]
+pub async fn create_user(
+    form: web::Form<CreateUserFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let CreateUserFormData {
+        username,
+        email,
+        password,
+        password_check,
+        role,
+    } = form.0;
+    let role = Role::try_from(role).map_err(e400)?;
+    let username = username.trim();
+    if username.is_empty() {
+        FlashMessage::error("The username can't be empty.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let email = match email.filter(|e| !e.trim().is_empty()) {
+        Some(email) => match SubscriberEmail::parse(email) {
+            Ok(email) => Some(email),
+            Err(_) => {
+                FlashMessage::error("The email address is not valid.").send();
+                return Ok(see_other("/admin/users"));
+            }
+        },
+        None => None,
+    };
+    if password.expose_secret().is_empty() {
+        FlashMessage::error("The password can't be empty.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other("/admin/users"));
+    }
+    match crate::authentication::create_user(username, email.as_ref(), password, role, &pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(_) => FlashMessage::info(format!(
+            "{} has been added as {}.",
+            encode_text(username),
+            role
+        ))
+        .send(),
+        None => FlashMessage::error("That username or email address is already taken.").send(),
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(
+    name = "Change a user's role",
+    skip(form, pool, current_user_id),
+    fields(role = %form.role)
+)]
+pub async fn change_user_role(
+    user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let role = Role::try_from(form.0.role).map_err(e400)?;
+    if *user_id == **current_user_id {
+        FlashMessage::error("You can't change your own role.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let n_updated_rows = sqlx::query!(
+        "UPDATE users SET role = $2 WHERE user_id = $1",
+        *user_id,
+        role.as_str()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to change the user's role.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The role has been changed.").send();
+    Ok(see_other("/admin/users"))
+}
+
+/// Users need an email address to reset a forgotten password.
+#[tracing::instrument(name = "Change a user's email address", skip(form, pool))]
+pub async fn change_user_email(
+    user_id: web::Path<Uuid>,
+    form: web::Form<EmailFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(_) => {
+            FlashMessage::error("The email address is not valid.").send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let result = sqlx::query!(
+        "UPDATE users SET email = $2 WHERE user_id = $1",
+        *user_id,
+        email.as_ref()
+    )
+    .execute(pool.get_ref())
+    .await;
+    match result {
+        Ok(r) if r.rows_affected() == 0 => return Ok(HttpResponse::NotFound().finish()),
+        Ok(_) => FlashMessage::info("The email address has been changed.").send(),
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some("users_email_key") => {
+            FlashMessage::error("That email address is already taken.").send()
+        }
+        Err(e) => {
+            return Err(e500(
+                anyhow::Error::new(e).context("Failed to change the user's email address."),
+            ))
+        }
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Disable a user", skip(pool, current_user_id))]
+pub async fn disable_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if *user_id == **current_user_id {
+        FlashMessage::error("You can't disable your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    set_disabled(&pool, *user_id, true).await
+}
+
+#[tracing::instrument(name = "Enable a user", skip(pool))]
+pub async fn enable_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_disabled(&pool, *user_id, false).await
+}
+
+async fn set_disabled(
+    pool: &PgPool,
+    user_id: Uuid,
+    disabled: bool,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_updated_rows = sqlx::query!(
+        "UPDATE users SET disabled = $2 WHERE user_id = $1",
+        user_id,
+        disabled
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the user's status.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if disabled {
+        FlashMessage::info("The user has been disabled.").send();
+    } else {
+        FlashMessage::info("The user has been enabled.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+/// Users who have not set up two-factor authentication yet will be asked to
+/// before they can do anything else.
+#[tracing::instrument(name = "Require two-factor authentication", skip(form, pool), fields(required = %form.required))]
+pub async fn require_two_factor(
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_two_factor_required(&pool, form.required)
+        .await
+        .map_err(e500)?;
+    if form.required {
+        FlashMessage::info("Two-factor authentication is now required for all users.").send();
+    } else {
+        FlashMessage::info("Two-factor authentication is now optional.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
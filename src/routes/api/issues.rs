@@ -0,0 +1,25 @@
+use super::require_scope;
+use crate::authentication::{ApiClient, ApiScope};
+use crate::routes::admin::{get_issues, ListParameters, ISSUES_PER_PAGE};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[tracing::instrument(name = "List newsletter issues through the API", skip_all)]
+pub async fn api_list_issues(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_scope(&client, ApiScope::ReadIssues)?;
+    let page = parameters.page();
+    let (issues, n_issues) = get_issues(&pool, page, parameters.order())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "issues": issues,
+        "page": page,
+        "per_page": ISSUES_PER_PAGE,
+        "total": n_issues,
+    })))
+}
@@ -0,0 +1,34 @@
+//! A JSON twin of parts of the admin area, authenticated with API tokens
+//! instead of a session cookie.
+mod issues;
+mod newsletters;
+mod subscribers;
+
+use crate::authentication::{ApiClient, ApiScope};
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+
+pub use issues::api_list_issues;
+pub use newsletters::api_publish_newsletter;
+pub use subscribers::api_list_subscribers;
+
+/// API errors come with a JSON body, e.g. `{"error": "..."}`.
+fn api_error<T>(status: StatusCode, e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    let response = HttpResponse::build(status).json(serde_json::json!({ "error": e.to_string() }));
+    InternalError::from_response(e, response).into()
+}
+
+fn require_scope(client: &ApiClient, scope: ApiScope) -> Result<(), actix_web::Error> {
+    if client.is_allowed(scope) {
+        Ok(())
+    } else {
+        Err(api_error(
+            StatusCode::FORBIDDEN,
+            format!("This request requires the {scope} scope."),
+        ))
+    }
+}
@@ -0,0 +1,101 @@
+use super::{api_error, require_scope};
+use crate::authentication::{ApiClient, ApiScope};
+use crate::configuration::IdempotencySettings;
+use crate::domain::{MergeTags, NewsletterContent};
+use crate::idempotency::{
+    save_response, try_processing, IdempotencyKey, NextAction, RequestFingerprint,
+};
+use crate::routes::admin::{insert_issue, parse_schedule};
+use crate::utils::e500;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+/// The same fields as the publishing form of the admin area.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct BodyData {
+    title: String,
+    markdown_content: Option<String>,
+    text_content: Option<String>,
+    html_content: Option<String>,
+    scheduled_for: Option<String>,
+    timezone: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip_all,
+    fields(user_id=%client.user_id)
+    )]
+pub async fn api_publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    idempotency: web::Data<IdempotencySettings>,
+    client: web::ReqData<ApiClient>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_scope(&client, ApiScope::PublishNewsletters)?;
+    let bad_request = |e| api_error(StatusCode::BAD_REQUEST, e);
+    let idempotency_key: IdempotencyKey = request
+        .headers()
+        .get("Idempotency-Key")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| bad_request("The Idempotency-Key header is missing.".into()))?
+        .to_owned()
+        .try_into()
+        .map_err(|e: anyhow::Error| bad_request(e.to_string()))?;
+    let fingerprint = RequestFingerprint::of(&body.0).map_err(e500)?;
+    let BodyData {
+        title,
+        markdown_content,
+        text_content,
+        html_content,
+        scheduled_for,
+        timezone,
+    } = body.0;
+    let content = NewsletterContent::parse(markdown_content, html_content, text_content)
+        .map_err(bad_request)?;
+    MergeTags::validate(&title)
+        .and_then(|_| content.validate_merge_tags())
+        .map_err(bad_request)?;
+    let schedule = parse_schedule(scheduled_for, timezone).map_err(bad_request)?;
+    let mut transaction = match try_processing(
+        &pool,
+        &idempotency_key,
+        &fingerprint,
+        client.user_id,
+        &idempotency,
+    )
+    .await
+    .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        NextAction::InProgress => {
+            return Ok(HttpResponse::Conflict()
+                .insert_header((RETRY_AFTER, idempotency.retry_after().as_secs()))
+                .json(serde_json::json!({
+                    "error": "A request with the same idempotency key is still being processed."
+                })))
+        }
+        NextAction::PayloadMismatch => {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "The idempotency key was already used for a different request.",
+            ))
+        }
+    };
+    let issue_id = insert_issue(&mut transaction, &title, &content, schedule.as_ref())
+        .await
+        .map_err(e500)?;
+    let response = HttpResponse::Accepted().json(serde_json::json!({
+        "newsletter_issue_id": issue_id,
+        "status": if schedule.is_some() { "scheduled" } else { "published" },
+        "scheduled_for": schedule.as_ref().map(|s| s.send_at()),
+    }));
+    let response = save_response(transaction, &idempotency_key, client.user_id, response)
+        .await
+        .map_err(e500)?;
+    Ok(response)
+}
@@ -0,0 +1,92 @@
+use super::require_scope;
+use crate::authentication::{ApiClient, ApiScope};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const SUBSCRIBERS_PER_PAGE: i64 = 100;
+
+#[derive(serde::Deserialize)]
+pub struct ListParameters {
+    page: Option<i64>,
+    /// e.g. `confirmed`, to leave out pending and unsubscribed addresses.
+    status: Option<String>,
+}
+
+impl ListParameters {
+    /// Capped so that the page offset cannot overflow.
+    fn page(&self) -> i64 {
+        self.page
+            .unwrap_or(1)
+            .clamp(1, i64::MAX / SUBSCRIBERS_PER_PAGE)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List subscribers through the API", skip_all)]
+pub async fn api_list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    require_scope(&client, ApiScope::ReadSubscribers)?;
+    let page = parameters.page();
+    let (subscribers, n_subscribers) = get_subscribers(&pool, page, parameters.status.as_deref())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "subscribers": subscribers,
+        "page": page,
+        "per_page": SUBSCRIBERS_PER_PAGE,
+        "total": n_subscribers,
+    })))
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(
+    pool: &PgPool,
+    page: i64,
+    status: Option<&str>,
+) -> Result<(Vec<Subscriber>, i64), anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at, email
+        LIMIT $2
+        OFFSET $3
+        "#,
+        status,
+        SUBSCRIBERS_PER_PAGE,
+        (page - 1) * SUBSCRIBERS_PER_PAGE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    let n_subscribers = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        "#,
+        status
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers.")?
+    .count;
+    Ok((subscribers, n_subscribers))
+}
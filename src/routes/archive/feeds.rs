@@ -0,0 +1,112 @@
+use super::{archive_link, get_published_issues, PublishedIssue, FEED_TITLE};
+use crate::domain::MergeTags;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const FEED_LENGTH: i64 = 20;
+
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let issues = get_published_issues(&pool, FEED_LENGTH, 0)
+        .await
+        .map_err(e500)?;
+    let updated = issues
+        .first()
+        .map(|issue| issue.published_at)
+        .unwrap_or_else(Utc::now);
+    let mut entries = String::new();
+    for issue in &issues {
+        let link = archive_link(base_url, &issue.slug);
+        writeln!(
+            entries,
+            r#"<entry>
+    <title>{title}</title>
+    <link href="{link}"/>
+    <id>urn:uuid:{id}</id>
+    <updated>{updated}</updated>
+    <content type="html">{content}</content>
+</entry>"#,
+            title = encode_text(&feed_title(issue, base_url)),
+            link = encode_double_quoted_attribute(&link),
+            id = issue.newsletter_issue_id,
+            updated = issue.published_at.to_rfc3339(),
+            content = encode_text(&feed_content(issue, base_url)),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{FEED_TITLE}</title>
+<link href="{base_url}/feed.atom" rel="self"/>
+<link href="{base_url}/archive"/>
+<id>{base_url}/archive</id>
+<updated>{updated}</updated>
+<author><name>{FEED_TITLE}</name></author>
+{entries}</feed>
+"#,
+            base_url = encode_double_quoted_attribute(base_url),
+            updated = updated.to_rfc3339(),
+        )))
+}
+
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let issues = get_published_issues(&pool, FEED_LENGTH, 0)
+        .await
+        .map_err(e500)?;
+    let mut items = String::new();
+    for issue in &issues {
+        let link = encode_text(&archive_link(base_url, &issue.slug)).into_owned();
+        writeln!(
+            items,
+            r#"<item>
+    <title>{title}</title>
+    <link>{link}</link>
+    <guid isPermaLink="true">{link}</guid>
+    <pubDate>{published_at}</pubDate>
+    <description>{content}</description>
+</item>"#,
+            title = encode_text(&feed_title(issue, base_url)),
+            published_at = issue.published_at.to_rfc2822(),
+            content = encode_text(&feed_content(issue, base_url)),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+<channel>
+<title>{FEED_TITLE}</title>
+<link>{base_url}/archive</link>
+<description>Past issues of the {FEED_TITLE}</description>
+{items}</channel>
+</rss>
+"#,
+            base_url = encode_text(base_url),
+        )))
+}
+
+fn feed_title(issue: &PublishedIssue, base_url: &str) -> String {
+    MergeTags::anonymous(base_url).render_text(&issue.title)
+}
+
+/// Sanitized like the archive page, as feed readers render it as HTML.
+fn feed_content(issue: &PublishedIssue, base_url: &str) -> String {
+    ammonia::clean(&MergeTags::anonymous(base_url).render_html(&issue.html_content))
+}
@@ -0,0 +1,72 @@
+mod feeds;
+mod pages;
+
+pub use feeds::{atom_feed, rss_feed};
+pub use pages::{archive, archived_issue};
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const FEED_TITLE: &str = "zero2prod newsletter";
+
+struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    html_content: String,
+    slug: String,
+    published_at: DateTime<Utc>,
+}
+
+pub fn archive_link(base_url: &str, slug: &str) -> String {
+    format!("{}/archive/{}", base_url, slug)
+}
+
+/// Drafts, scheduled and cancelled issues never make it to the public archive.
+#[tracing::instrument(name = "Get published issues", skip(pool))]
+async fn get_published_issues(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<PublishedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            html_content,
+            slug as "slug!",
+            published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE
+            status = 'published' AND
+            slug IS NOT NULL AND
+            published_at IS NOT NULL
+        ORDER BY published_at DESC
+        LIMIT $1
+        OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Count published issues", skip(pool))]
+async fn count_published_issues(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM newsletter_issues
+        WHERE
+            status = 'published' AND
+            slug IS NOT NULL AND
+            published_at IS NOT NULL
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.count)
+}
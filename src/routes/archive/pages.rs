@@ -0,0 +1,146 @@
+use super::{archive_link, count_published_issues, get_published_issues, FEED_TITLE};
+use crate::domain::MergeTags;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const ISSUES_PER_PAGE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+impl ArchiveParameters {
+    /// Capped so that the page offset cannot overflow.
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, i64::MAX / ISSUES_PER_PAGE)
+    }
+}
+
+pub async fn archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = parameters.page();
+    let issues = get_published_issues(&pool, ISSUES_PER_PAGE, (page - 1) * ISSUES_PER_PAGE)
+        .await
+        .map_err(e500)?;
+    let n_issues = count_published_issues(&pool).await.map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="{}">{}</a> - {}</li>"#,
+            encode_double_quoted_attribute(&archive_link(&base_url.0, &issue.slug)),
+            encode_text(&issue.title),
+            issue.published_at.format("%Y-%m-%d"),
+        )
+        .unwrap();
+    }
+    let n_pages = ((n_issues + ISSUES_PER_PAGE - 1) / ISSUES_PER_PAGE).max(1);
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/archive?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="/archive?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>{FEED_TITLE}</title>
+                    <link rel="alternate" type="application/atom+xml" href="/feed.atom">
+                    <link rel="alternate" type="application/rss+xml" href="/feed.rss">
+                </head>
+                <body>
+                    <h1>Past issues</h1>
+                    <ul>
+                        {issues_html}
+                    </ul>
+                    <p>{pagination_html}</p>
+                    <p><a href="/feed.atom">Atom feed</a> - <a href="/feed.rss">RSS feed</a></p>
+                </body>
+            </html>
+        "#,
+        )))
+}
+
+struct ArchivedIssue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT title, html_content, published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE
+            slug = $1 AND
+            status = 'published' AND
+            published_at IS NOT NULL
+        "#,
+        slug.as_str()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    let issue = match issue {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let merge_tags = MergeTags::anonymous(&base_url.0);
+    // Hand-written HTML is stored as it was sent, styling included, so it is
+    // sanitized here: the archive shares its origin with the admin area.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>{title}</title>
+                </head>
+                <body>
+                    <h1>{title}</h1>
+                    <p>Published on {published_at}</p>
+                    {content}
+                    <p><a href="/archive">&lt; - All issues</a></p>
+                </body>
+            </html>
+        "#,
+            title = encode_text(&merge_tags.render_text(&issue.title)),
+            published_at = issue.published_at.format("%Y-%m-%d"),
+            content = ammonia::clean(&merge_tags.render_html(&issue.html_content)),
+        )))
+}
@@ -1,3 +1,5 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
@@ -38,7 +40,47 @@ pub async fn login_form(flash_message: IncomingFlashMessages) -> HttpResponse {
 
                         <button type="submit">Login</button>
                     </form>
+                    <p><a href="/password-reset">Forgot your password?</a></p>
                 </body>
             </html>"#
         ))
 }
+
+pub async fn login_two_factor_form(
+    session: TypedSession,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_two_factor().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut error_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Two-factor authentication</title>
+                </head>
+                <body>
+                    {error_html}
+                    <form action="/login/two-factor" method="post">
+                        <label>Enter the code from your authenticator app, or one of your recovery codes
+                            <input
+                                type="text"
+                                autocomplete="one-time-code"
+                                name="code"
+                            >
+                        </label>
+
+                        <button type="submit">Verify</button>
+                    </form>
+                </body>
+            </html>"#
+        )))
+}
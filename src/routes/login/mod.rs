@@ -1,5 +1,5 @@
 mod get;
 mod post;
 
-pub use get::login_form;
-pub use post::login;
+pub use get::{login_form, login_two_factor_form};
+pub use post::{login, login_two_factor};
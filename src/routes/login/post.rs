@@ -1,11 +1,21 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{
+    get_two_factor_status, record_session, validate_credentials, verify_second_factor, AuthError,
+    Credentials, LoginAccount, LoginThrottle, ThrottleError, TwoFactorStatus,
+};
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
-use actix_web::{error::InternalError, web, HttpResponse};
+use crate::utils::client_ip;
+use actix_web::{error::InternalError, web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use reqwest::header::LOCATION;
-use secrecy::Secret;
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use reqwest::header::{LOCATION, USER_AGENT};
+use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How long users have to enter their second factor after their password.
+const PENDING_TWO_FACTOR_TTL_MINUTES: i64 = 5;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
@@ -14,32 +24,63 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, login_throttle, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
     )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let ip = client_ip(&request);
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
+    let account = LoginAccount::Username(&username);
+    login_throttle
+        .wait_for_turn(&account, &ip)
+        .await
+        .map_err(|e| login_redirect(e.into()))?;
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            login_throttle
+                .record_success(&account)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            let two_factor_status = get_two_factor_status(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            if let TwoFactorStatus::Enabled = two_factor_status {
+                session
+                    .insert_pending_two_factor(user_id, Utc::now())
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two-factor"))
+                    .finish());
+            }
+            start_session(&session, user_id, &pool, &request)
+                .await
+                .map_err(login_redirect)?;
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    login_throttle
+                        .record_failure(&account, &ip)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(login_redirect(e))
@@ -47,6 +88,93 @@ pub async fn login(
     }
 }
 
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(
+    skip(form, pool, session, login_throttle, request),
+    fields(user_id=tracing::field::Empty)
+    )]
+pub async fn login_two_factor(
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let pending = session
+        .get_pending_two_factor()
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+    let user_id = match pending {
+        Some((user_id, started_at))
+            if Utc::now() - started_at < Duration::minutes(PENDING_TWO_FACTOR_TTL_MINUTES) =>
+        {
+            user_id
+        }
+        _ => {
+            session.remove_pending_two_factor();
+            return Err(login_redirect(LoginError::TwoFactorExpired));
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let ip = client_ip(&request);
+    let account = LoginAccount::UserId(user_id);
+    login_throttle
+        .wait_for_turn(&account, &ip)
+        .await
+        .map_err(|e| login_redirect(e.into()))?;
+    let is_valid = verify_second_factor(&pool, user_id, form.0.code.expose_secret())
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if !is_valid {
+        login_throttle
+            .record_failure(&account, &ip)
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+        FlashMessage::error("The code is not valid.").send();
+        let response = HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login/two-factor"))
+            .finish();
+        let e = LoginError::AuthError(anyhow::anyhow!("Invalid second factor."));
+        return Err(InternalError::from_response(e, response));
+    }
+    login_throttle
+        .record_success(&account)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    session.renew();
+    session.remove_pending_two_factor();
+    start_session(&session, user_id, &pool, &request)
+        .await
+        .map_err(login_redirect)?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/dashboard"))
+        .finish())
+}
+
+async fn start_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    pool: &PgPool,
+    request: &HttpRequest,
+) -> Result<(), LoginError> {
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("unknown");
+    let session_id = record_session(pool, user_id, &client_ip(request), user_agent)
+        .await
+        .context("Failed to record the new session")?;
+    session
+        .insert_user_id(user_id)
+        .and_then(|_| session.insert_logged_in_at(Utc::now()))
+        .and_then(|_| session.insert_session_id(session_id))
+        .map_err(|e| LoginError::UnexpectedError(e.into()))
+}
+
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
@@ -59,10 +187,23 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Your login attempt has expired. Please log in again.")]
+    TwoFactorExpired,
+    #[error("Too many failed login attempts. Please try again later.")]
+    TooManyAttempts(#[source] ThrottleError),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl From<ThrottleError> for LoginError {
+    fn from(e: ThrottleError) -> Self {
+        match e {
+            ThrottleError::LockedOut => LoginError::TooManyAttempts(e),
+            ThrottleError::UnexpectedError(e) => LoginError::UnexpectedError(e),
+        }
+    }
+}
+
 impl std::fmt::Debug for LoginError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -1,16 +1,32 @@
 mod admin;
+mod api;
+mod archive;
 mod health_check;
 mod home;
 mod login;
+mod password_reset;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 
 pub use admin::{
-    admin_dashboard, change_password, change_password_form, log_out, publish_newsletter,
-    submit_newsletter_form,
+    admin_dashboard, api_tokens, cancel_scheduled_issue, change_password, change_password_form,
+    change_user_email, change_user_role, clone_issue, confirm_two_factor, create_api_token,
+    create_draft, create_user, delete_issue, delivery_failures, disable_two_factor, disable_user,
+    drafts, edit_draft_form, enable_user, issue_delivery_report, issue_details, list_issues,
+    log_out, preview_draft, publish_draft, publish_newsletter, requeue_delivery_failure,
+    require_two_factor, reschedule_issue, revoke_api_token, revoke_other_sessions, revoke_session,
+    save_draft, scheduled_issues, send_test_email, set_up_two_factor, submit_newsletter_form,
+    two_factor_settings, users,
 };
+pub use api::{api_list_issues, api_list_subscribers, api_publish_newsletter};
+pub use archive::{archive, archive_link, archived_issue, atom_feed, rss_feed};
 pub use health_check::health_check;
 pub use home::home;
-pub use login::{login, login_form};
+pub use login::{login, login_form, login_two_factor, login_two_factor_form};
+pub use password_reset::{
+    password_reset_form, request_password_reset, reset_password, reset_password_form,
+};
 pub use subscriptions::{error_chain_fmt, subscribe};
 pub use subscriptions_confirm::confirm;
+pub use subscriptions_unsubscribe::{unsubscribe, unsubscribe_link};
@@ -0,0 +1,89 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use html_escape::encode_double_quoted_attribute;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+pub async fn password_reset_form(flash_message: IncomingFlashMessages) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Forgot your password?</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <p>Enter the email address of your account and we will send you a link to choose a new password.</p>
+                    <form action="/password-reset" method="post">
+                        <label>Email
+                            <input
+                                type="email"
+                                placeholder="Enter your email address"
+                                name="email"
+                            >
+                        </label>
+                        <button type="submit">Send me a reset link</button>
+                    </form>
+                    <p><a href="/login">&lt; - Back to login</a></p>
+                </body>
+            </html>"#
+        ))
+}
+
+pub async fn reset_password_form(
+    parameters: web::Query<Parameters>,
+    flash_message: IncomingFlashMessages,
+) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Choose a new password</title>
+                </head>
+                <body>
+                    {msg_html}
+                    <form action="/password-reset/confirm" method="post">
+                        <input hidden type="text" name="token" value="{token}">
+                        <label>New password
+                            <input
+                                type="password"
+                                placeholder="Enter new password"
+                                name="new_password"
+                            >
+                        </label>
+                        <br>
+                        <label>Confirm new password
+                            <input
+                                type="password"
+                                placeholder="Type the new password again"
+                                name="new_password_check"
+                            >
+                        </label>
+                        <br>
+                        <button type="submit">Reset password</button>
+                    </form>
+                </body>
+            </html>"#,
+            token = encode_double_quoted_attribute(&parameters.token),
+        ))
+}
@@ -0,0 +1,12 @@
+mod get;
+mod post;
+
+use sha2::{Digest, Sha256};
+
+pub use get::{password_reset_form, reset_password_form};
+pub use post::{request_password_reset, reset_password};
+
+/// Reset tokens are looked up by their digest, see `password_reset_tokens`.
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
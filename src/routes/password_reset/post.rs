@@ -0,0 +1,287 @@
+use super::hash_token;
+use crate::authentication::{revoke_all_sessions, LoginAccount, LoginThrottle, ThrottleError};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailSender;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::Instrument;
+use uuid::Uuid;
+
+const PASSWORD_RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, pool, email_client, base_url, login_throttle, request)
+)]
+pub async fn request_password_reset(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    login_throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Answer the same way whether or not the address belongs to someone:
+    // the response must not reveal who has an account.
+    let message = FlashMessage::info(
+        "If an account uses this email address, \
+        we have sent it a link to reset its password.",
+    );
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(_) => {
+            message.send();
+            return Ok(see_other("/login"));
+        }
+    };
+    // Every request counts, so that nobody can flood an inbox with links.
+    let ip = client_ip(&request);
+    let email_key = email.as_ref().to_lowercase();
+    let account = LoginAccount::PasswordReset(&email_key);
+    match login_throttle.wait_for_turn(&account, &ip).await {
+        Ok(()) => {}
+        Err(ThrottleError::LockedOut) => {
+            FlashMessage::error("Too many password reset requests. Please try again later.").send();
+            return Ok(see_other("/password-reset"));
+        }
+        Err(ThrottleError::UnexpectedError(e)) => return Err(e500(e)),
+    }
+    login_throttle
+        .record_failure(&account, &ip)
+        .await
+        .map_err(e500)?;
+    // The link is sent in the background: how long the response takes, and
+    // whether the email provider is up, must not give accounts away either.
+    tokio::spawn(
+        async move {
+            if let Err(e) =
+                send_password_reset_link(&pool, email_client.as_ref(), &email, &base_url.0).await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a password reset link"
+                );
+            }
+        }
+        .in_current_span(),
+    );
+    message.send();
+    Ok(see_other("/login"))
+}
+
+/// Does nothing if no account uses the email address.
+async fn send_password_reset_link(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    email: &SubscriberEmail,
+    base_url: &str,
+) -> Result<(), anyhow::Error> {
+    let user_id = get_user_id_by_email(pool, email)
+        .await
+        .context("Failed to look up the user by email")?;
+    if let Some(user_id) = user_id {
+        let token = generate_password_reset_token();
+        store_token(pool, user_id, &token)
+            .await
+            .context("Failed to store the password reset token")?;
+        send_password_reset_email(email_client, email, base_url, &token)
+            .await
+            .context("Failed to send the password reset email")?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Reset a password", skip(form, pool))]
+pub async fn reset_password(
+    form: web::Form<ResetFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.0;
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        let query = serde_urlencoded::to_string([("token", &token)]).map_err(e500)?;
+        return Ok(see_other(&format!("/password-reset/confirm?{}", query)));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let user_id = match get_valid_token(&mut transaction, &hash_token(&token))
+        .await
+        .context("Failed to retrieve the password reset token")
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => {
+            FlashMessage::error(
+                "This password reset link is invalid or has expired. Please ask for a new one.",
+            )
+            .send();
+            return Ok(see_other("/password-reset"));
+        }
+    };
+    crate::authentication::change_password(user_id, new_password, &mut transaction)
+        .await
+        .map_err(e500)?;
+    use_all_tokens(&mut transaction, user_id)
+        .await
+        .context("Failed to mark the password reset tokens as used")
+        .map_err(e500)?;
+    revoke_all_sessions(&mut transaction, user_id)
+        .await
+        .context("Failed to revoke the user's sessions")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset")
+        .map_err(e500)?;
+    FlashMessage::info("Your password has been reset. You can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(name = "Get user by email", skip(pool, email))]
+async fn get_user_id_by_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT user_id FROM users WHERE email = $1 AND NOT disabled",
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.user_id))
+}
+
+fn generate_password_reset_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+#[tracing::instrument(name = "Store password reset token", skip(pool, token))]
+async fn store_token(pool: &PgPool, user_id: Uuid, token: &str) -> Result<(), sqlx::Error> {
+    let created_at = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        hash_token(token),
+        user_id,
+        created_at,
+        created_at + Duration::minutes(PASSWORD_RESET_TOKEN_TTL_MINUTES),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Send a password reset email", skip(email_client, email, token))]
+async fn send_password_reset_email(
+    email_client: &dyn EmailSender,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let reset_link = format!("{}/password-reset/confirm?token={}", base_url, token);
+    email_client
+        .send_email(
+            email,
+            "Reset your password",
+            &format!(
+                r#"Someone asked to reset the password of your account.<br/>
+                Click <a href="{reset_link}">here</a> to choose a new one.
+                The link expires in {PASSWORD_RESET_TOKEN_TTL_MINUTES} minutes.<br/>
+                If it wasn't you, you can safely ignore this email."#,
+            ),
+            &format!(
+                r#"Someone asked to reset the password of your account.
+                Visit {reset_link} to choose a new one.
+                The link expires in {PASSWORD_RESET_TOKEN_TTL_MINUTES} minutes.
+                If it wasn't you, you can safely ignore this email."#,
+            ),
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns the user the token was issued to, as long as it is unused and
+/// has not expired. The token stays locked until the transaction ends, so
+/// it can't be used twice concurrently.
+#[tracing::instrument(name = "Get valid password reset token", skip_all)]
+async fn get_valid_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    struct StoredToken {
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+        used_at: Option<DateTime<Utc>>,
+    }
+    let token = sqlx::query_as!(
+        StoredToken,
+        r#"
+        SELECT user_id, expires_at, used_at
+        FROM password_reset_tokens
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+        token_hash
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(token
+        .filter(|t| t.used_at.is_none() && t.expires_at > Utc::now())
+        .map(|t| t.user_id))
+}
+
+/// Once the password has been reset, any other link we sent is useless.
+#[tracing::instrument(name = "Use all password reset tokens", skip(transaction))]
+async fn use_all_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
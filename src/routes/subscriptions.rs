@@ -1,15 +1,17 @@
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::email_client::EmailSender;
 use crate::startup::ApplicationBaseUrl;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::Deserialize;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+const SUBSCRIPTION_TOKEN_TTL_HOURS: i64 = 24;
+
 #[derive(Deserialize)]
 pub struct FormData {
     name: String,
@@ -27,7 +29,7 @@ pub struct FormData {
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
     let new_subscriber = form.0.try_into().map_err(SubscribeError::Validation)?;
@@ -35,9 +37,26 @@ pub async fn subscribe(
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
+    let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction)
         .await
-        .context("Failed to insert new subscriber in the database")?;
+        .context("Failed to insert new subscriber in the database")?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let existing = get_existing_subscriber(&new_subscriber, &mut transaction)
+                .await
+                .context("Failed to look up an existing subscriber in the database")?;
+            // Answer exactly as for a new subscriber: the response must not
+            // reveal whether an email address is already on the list.
+            if existing.status == "confirmed" {
+                return Ok(HttpResponse::Ok().finish());
+            }
+            reset_pending_subscriber(&mut transaction, existing.id)
+                .await
+                .context("Failed to reset the confirmation of an existing subscriber")?;
+            existing.id
+        }
+    };
 
     let subsciption_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subsciption_token)
@@ -50,7 +69,7 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction to store a new subscriber")?;
 
     send_confirmation_email(
-        &email_client,
+        email_client.as_ref(),
         new_subscriber,
         &base_url.0,
         &subsciption_token,
@@ -70,6 +89,63 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+}
+
+#[tracing::instrument(
+    name = "Looking up an existing subscriber with the same email",
+    skip(new_subscriber, transaction)
+)]
+async fn get_existing_subscriber(
+    new_subscriber: &NewSubscriber,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<ExistingSubscriber, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT id, status
+        FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_one(transaction)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Issuing a fresh confirmation to an existing subscriber",
+    skip(transaction)
+)]
+async fn reset_pending_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'pending_confirmation'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Returns `None` if the email address is already on the list. A concurrent
+/// sign-up for the same address waits for the other one to commit, instead of
+/// failing on the unique constraint.
 #[tracing::instrument(
     name = "Saving the new subscriber details in the database"
     skip(new_subscriber, transaction),
@@ -77,21 +153,22 @@ impl TryFrom<FormData> for NewSubscriber {
 async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
         r#"
         INSERT INTO subscriptions (id, email, name, subscribed_at, status)
         VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-    .execute(transaction)
+    .fetch_optional(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok(row.map(|r| r.id))
 }
 
 #[tracing::instrument(
@@ -99,11 +176,11 @@ async fn insert_subscriber(
     skip(email_client, new_subscriber)
 )]
 async fn send_confirmation_email(
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token,
@@ -123,8 +200,10 @@ async fn send_confirmation_email(
             "#,
                 confirmation_link
             ),
+            &[],
         )
-        .await
+        .await?;
+    Ok(())
 }
 
 fn generate_subscription_token() -> String {
@@ -144,10 +223,16 @@ async fn store_token(
     subscriber_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
+    let created_at = Utc::now();
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        created_at,
+        created_at + Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS),
     )
     .execute(transaction)
     .await
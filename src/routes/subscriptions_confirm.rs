@@ -1,4 +1,6 @@
+use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -10,13 +12,14 @@ pub struct Parameters {
 
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters))]
 pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
+    let token = match get_token(&pool, &parameters.subscription_token).await {
+        Ok(token) => token,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    match id {
+    match token {
         None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
+        Some(StoredToken { expires_at, .. }) if expires_at < Utc::now() => expired_token_page(),
+        Some(StoredToken { subscriber_id, .. }) => {
             if confirm_subscriber(&pool, subscriber_id).await.is_err() {
                 return HttpResponse::InternalServerError().finish();
             }
@@ -25,10 +28,31 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
     }
 }
 
+fn expired_token_page() -> HttpResponse {
+    HttpResponse::Gone().content_type(ContentType::html()).body(
+        r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Confirmation link expired</title>
+                </head>
+                <body>
+                    <p>This confirmation link has expired.</p>
+                    <p>Subscribe again with the same email address to receive a new one.</p>
+                </body>
+            </html>"#,
+    )
+}
+
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
 async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+        r#"
+        UPDATE subscriptions
+        SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscriber_id
     )
     .execute(pool)
@@ -40,13 +64,23 @@ async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sq
     Ok(())
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-async fn get_subscriber_id_from_token(
+struct StoredToken {
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get stored token", skip(subscription_token, pool))]
+async fn get_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1",
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    let result = sqlx::query_as!(
+        StoredToken,
+        r#"
+        SELECT subscriber_id, expires_at
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        "#,
         subscription_token
     )
     .fetch_optional(pool)
@@ -55,5 +89,5 @@ async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result)
 }
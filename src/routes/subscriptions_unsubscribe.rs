@@ -0,0 +1,105 @@
+use crate::domain::UnsubscribeToken;
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct Parameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let Parameters {
+        subscriber_id,
+        token,
+    } = parameters.0;
+    let token = UnsubscribeToken::parse(token).map_err(UnsubscribeError::InvalidToken)?;
+    if !token.verify(subscriber_id, &hmac_secret.0) {
+        return Err(UnsubscribeError::InvalidToken(
+            "The unsubscribe token does not match the subscriber.".into(),
+        ));
+    }
+    mark_subscriber_as_unsubscribed(&pool, subscriber_id)
+        .await
+        .context("Failed to mark the subscriber as unsubscribed")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Unsubscribed</title>
+                </head>
+                <body>
+                    <p>You have been unsubscribed, you will not receive any further issues.</p>
+                </body>
+            </html>"#,
+    ))
+}
+
+pub fn unsubscribe_link(
+    base_url: &str,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>,
+) -> String {
+    let token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        base_url,
+        subscriber_id,
+        token.as_ref()
+    )
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("{0}")]
+    InvalidToken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken(_) => reqwest::StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
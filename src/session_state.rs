@@ -2,6 +2,7 @@ use actix_session::SessionExt;
 use actix_session::{Session, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
@@ -17,6 +18,9 @@ impl FromRequest for TypedSession {
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const LOGGED_IN_AT_KEY: &'static str = "logged_in_at";
+    const PENDING_TWO_FACTOR_KEY: &'static str = "pending_two_factor";
+    const SESSION_ID_KEY: &'static str = "session_id";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -30,6 +34,45 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Lets us tell apart the sessions that were opened before a password reset.
+    pub fn insert_logged_in_at(
+        &self,
+        logged_in_at: DateTime<Utc>,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LOGGED_IN_AT_KEY, logged_in_at)
+    }
+
+    pub fn get_logged_in_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        self.0.get(Self::LOGGED_IN_AT_KEY)
+    }
+
+    /// Links the session to its entry in the `user_sessions` index.
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// Remembers who got their password right while we wait for their second factor.
+    pub fn insert_pending_two_factor(
+        &self,
+        user_id: Uuid,
+        started_at: DateTime<Utc>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::PENDING_TWO_FACTOR_KEY, (user_id, started_at))
+    }
+
+    pub fn get_pending_two_factor(&self) -> Result<Option<(Uuid, DateTime<Utc>)>, SessionGetError> {
+        self.0.get(Self::PENDING_TWO_FACTOR_KEY)
+    }
+
+    pub fn remove_pending_two_factor(&self) {
+        self.0.remove(Self::PENDING_TWO_FACTOR_KEY);
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
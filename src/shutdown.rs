@@ -0,0 +1,82 @@
+use tokio::sync::watch;
+
+/// Asks every task holding a `Shutdown` to stop.
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+/// Tells a long-running task when to stop: it is up to the task to do so at a
+/// point where it leaves no work half done.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+pub fn shutdown_channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(sender), Shutdown(receiver))
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    /// For tasks that run until the process exits, e.g. in tests.
+    pub fn never() -> Self {
+        shutdown_channel().1
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once a shutdown has been requested, which never happens if
+    /// the trigger is dropped first.
+    pub async fn requested(&mut self) {
+        while !*self.0.borrow_and_update() {
+            if self.0.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Resolves on SIGTERM, sent when a container is stopped, or on SIGINT (Ctrl-C).
+pub async fn shutdown_signal() -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigterm.recv() => Ok(()),
+            ctrl_c = tokio::signal::ctrl_c() => ctrl_c,
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shutdown_channel, Shutdown};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn a_requested_shutdown_is_seen_by_every_task() {
+        let (trigger, mut shutdown) = shutdown_channel();
+        let mut other = shutdown.clone();
+        assert!(!shutdown.is_requested());
+
+        trigger.trigger();
+
+        assert!(shutdown.is_requested());
+        shutdown.requested().await;
+        other.requested().await;
+    }
+
+    #[tokio::test]
+    async fn a_dropped_trigger_never_requests_a_shutdown() {
+        let mut shutdown = Shutdown::never();
+        let outcome = tokio::time::timeout(Duration::from_millis(50), shutdown.requested()).await;
+        assert!(outcome.is_err());
+    }
+}
@@ -1,12 +1,27 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
+use crate::authentication::{
+    reject_anonymous_users, reject_invalid_api_tokens, reject_non_owners,
+    reject_users_without_two_factor, reject_viewers, LoginThrottle, SESSION_TTL_HOURS,
+};
+use crate::configuration::{ApplicationSettings, DatabaseSettings, IdempotencySettings, Settings};
+use crate::email_client::EmailSender;
+use crate::idempotency::idempotent;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, submit_newsletter_form, subscribe,
+    admin_dashboard, api_list_issues, api_list_subscribers, api_publish_newsletter, api_tokens,
+    archive, archived_issue, atom_feed, cancel_scheduled_issue, change_password,
+    change_password_form, change_user_email, change_user_role, clone_issue, confirm,
+    confirm_two_factor, create_api_token, create_draft, create_user, delete_issue,
+    delivery_failures, disable_two_factor, disable_user, drafts, edit_draft_form, enable_user,
+    health_check, home, issue_delivery_report, issue_details, list_issues, log_out, login,
+    login_form, login_two_factor, login_two_factor_form, password_reset_form, preview_draft,
+    publish_draft, publish_newsletter, request_password_reset, requeue_delivery_failure,
+    require_two_factor, reschedule_issue, reset_password, reset_password_form, revoke_api_token,
+    revoke_other_sessions, revoke_session, rss_feed, save_draft, scheduled_issues, send_test_email,
+    set_up_two_factor, submit_newsletter_form, subscribe, two_factor_settings, unsubscribe, users,
 };
+use crate::shutdown::Shutdown;
+use actix_session::config::BrowserSession;
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
-use actix_web::cookie::Key;
+use actix_web::cookie::{time, Key};
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
@@ -15,7 +30,8 @@ use actix_web_lab::middleware::from_fn;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 pub struct HmacSecret(pub Secret<String>);
@@ -38,8 +54,8 @@ impl Application {
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
+            configuration.application,
+            configuration.idempotency,
             configuration.redis_uri,
         )
         .await?;
@@ -51,7 +67,14 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    /// Once a shutdown is requested, stops accepting connections and lets the
+    /// requests in flight complete, within `application.shutdown_timeout`.
+    pub async fn run_until_stopped(self, mut shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.requested().await;
+            handle.stop(true).await;
+        });
         self.server.await
     }
 }
@@ -64,51 +87,176 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
+    email_client: Arc<dyn EmailSender>,
+    application: ApplicationSettings,
+    idempotency: IdempotencySettings,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
+    let email_client = web::Data::from(email_client);
+    let shutdown_timeout = application.shutdown_timeout();
+    let ApplicationSettings {
+        base_url,
+        hmac_secret,
+        login_throttling,
+        trusted_proxies,
+        ..
+    } = application;
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let trusted_proxies = web::Data::new(TrustedProxies(trusted_proxies));
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
     let hmac_secret = web::Data::new(HmacSecret(hmac_secret));
+    let idempotency = web::Data::new(idempotency);
+    let login_throttle = web::Data::new(LoginThrottle::new(&redis_uri, login_throttling).await?);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .session_lifecycle(
+                        BrowserSession::default()
+                            .state_ttl(time::Duration::hours(SESSION_TTL_HOURS)),
+                    )
+                    .build(),
+            )
             .wrap(TracingLogger::default())
             .route("/health-check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
             .route("/home", web::get().to(home))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/feed.rss", web::get().to(rss_feed))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/two-factor", web::get().to(login_two_factor_form))
+            .route("/login/two-factor", web::post().to(login_two_factor))
+            .route("/password-reset", web::get().to(password_reset_form))
+            .route("/password-reset", web::post().to(request_password_reset))
+            .route(
+                "/password-reset/confirm",
+                web::get().to(reset_password_form),
+            )
+            .route("/password-reset/confirm", web::post().to(reset_password))
             .service(
                 web::scope("/admin")
+                    .wrap(from_fn(reject_viewers))
+                    .wrap(from_fn(reject_users_without_two_factor))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/password", web::get().to(change_password_form))
-                    .route("/password", web::post().to(change_password))
+                    .service(
+                        web::resource("/password")
+                            .wrap(from_fn(idempotent))
+                            .route(web::get().to(change_password_form))
+                            .route(web::post().to(change_password)),
+                    )
                     .route("/newsletters", web::get().to(submit_newsletter_form))
                     .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/logout", web::post().to(log_out)),
+                    .route("/newsletters/failures", web::get().to(delivery_failures))
+                    .route(
+                        "/newsletters/failures/requeue",
+                        web::post().to(requeue_delivery_failure),
+                    )
+                    .route("/newsletters/drafts", web::get().to(drafts))
+                    .route("/newsletters/drafts", web::post().to(create_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route("/newsletters/drafts/{draft_id}", web::post().to(save_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}/preview",
+                        web::get().to(preview_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/test",
+                        web::post().to(send_test_email),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/publish",
+                        web::post().to(publish_draft),
+                    )
+                    .route("/newsletters/issues", web::get().to(list_issues))
+                    .route(
+                        "/newsletters/issues/{issue_id}",
+                        web::get().to(issue_details),
+                    )
+                    .route(
+                        "/newsletters/issues/{issue_id}/clone",
+                        web::post().to(clone_issue),
+                    )
+                    .route(
+                        "/newsletters/issues/{issue_id}/delete",
+                        web::post().to(delete_issue),
+                    )
+                    .route(
+                        "/newsletters/issues/{issue_id}/delivery",
+                        web::get().to(issue_delivery_report),
+                    )
+                    .route("/newsletters/scheduled", web::get().to(scheduled_issues))
+                    .route(
+                        "/newsletters/scheduled/reschedule",
+                        web::post().to(reschedule_issue),
+                    )
+                    .route(
+                        "/newsletters/scheduled/cancel",
+                        web::post().to(cancel_scheduled_issue),
+                    )
+                    .route("/logout", web::post().to(log_out))
+                    .route("/sessions/revoke", web::post().to(revoke_session))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(revoke_other_sessions),
+                    )
+                    .route("/api-tokens", web::get().to(api_tokens))
+                    .route("/api-tokens", web::post().to(create_api_token))
+                    .route("/api-tokens/revoke", web::post().to(revoke_api_token))
+                    .route("/two-factor", web::get().to(two_factor_settings))
+                    .route("/two-factor/setup", web::post().to(set_up_two_factor))
+                    .route("/two-factor/confirm", web::post().to(confirm_two_factor))
+                    .route("/two-factor/disable", web::post().to(disable_two_factor))
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(idempotent))
+                            .wrap(from_fn(reject_non_owners))
+                            .route("", web::get().to(users))
+                            .route("", web::post().to(create_user))
+                            .route("/two-factor", web::post().to(require_two_factor))
+                            .route("/{user_id}/email", web::post().to(change_user_email))
+                            .route("/{user_id}/role", web::post().to(change_user_role))
+                            .route("/{user_id}/disable", web::post().to(disable_user))
+                            .route("/{user_id}/enable", web::post().to(enable_user)),
+                    ),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .route("/newsletters", web::post().to(api_publish_newsletter))
+                    .route("/issues", web::get().to(api_list_issues))
+                    .route("/subscribers", web::get().to(api_list_subscribers)),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(trusted_proxies.clone())
             .app_data(hmac_secret.clone())
+            .app_data(login_throttle.clone())
+            .app_data(idempotency.clone())
     })
+    // Signals are handled by the caller, to stop the background tasks as well.
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .listen(listener)?
     .run();
     Ok(server)
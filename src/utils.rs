@@ -1,4 +1,5 @@
-use actix_web::{http::header::LOCATION, HttpResponse};
+use crate::startup::TrustedProxies;
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse};
 
 pub fn e500<T>(e: T) -> actix_web::Error
 where
@@ -19,3 +20,34 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Anyone can send an `X-Forwarded-For` header, so it is only read when the
+/// peer is one of our trusted proxies. Each proxy appends the address it got
+/// the request from: the client is the last one that is not a trusted proxy.
+pub fn client_ip(request: &HttpRequest) -> String {
+    let peer_ip = match request.peer_addr() {
+        Some(peer_addr) => peer_addr.ip(),
+        None => return "unknown".into(),
+    };
+    let trusted_proxies = match request.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted_proxies) => &trusted_proxies.0,
+        None => return peer_ip.to_string(),
+    };
+    let mut client_ip = peer_ip;
+    let forwarded_ips = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for forwarded_ip in forwarded_ips.into_iter().rev() {
+        if !trusted_proxies.contains(&client_ip) {
+            break;
+        }
+        match forwarded_ip.trim().parse() {
+            Ok(ip) => client_ip = ip,
+            Err(_) => break,
+        }
+    }
+    client_ip.to_string()
+}
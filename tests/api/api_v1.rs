@@ -0,0 +1,417 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subsriber, create_unconfirmed_subscriber, spawn_app,
+    TestApp, TestUser,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::authentication::ApiScope;
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+    })
+}
+
+async fn token_id(app: &TestApp, user_id: Uuid) -> Uuid {
+    sqlx::query!(
+        "SELECT token_id FROM api_tokens WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .token_id
+}
+
+#[tokio::test]
+async fn requests_without_a_token_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/issues", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Bearer realm="api""#
+    );
+}
+
+#[tokio::test]
+async fn requests_with_an_unknown_token_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_api("/issues", "zp_not-a-real-token").await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_session_cookie_does_not_authenticate_api_requests() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/issues", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_can_only_be_used_within_their_scopes() {
+    // Given
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::ReadIssues])
+        .await;
+
+    // When
+    let response = app
+        .post_api_newsletters(&token, Some("key"), &newsletter_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "This request requires the newsletters:publish scope."
+    );
+    assert_eq!(app.get_api("/issues", &token).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn viewer_tokens_cannot_publish_newsletters() {
+    // Given
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    let token = app
+        .create_api_token(viewer.user_id, &[ApiScope::PublishNewsletters])
+        .await;
+
+    // When
+    let response = app
+        .post_api_newsletters(&token, Some("key"), &newsletter_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn publishing_through_the_api_sends_the_newsletter() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::PublishNewsletters])
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_api_newsletters(&token, Some("ci-run-1"), &newsletter_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "published");
+    assert!(Uuid::parse_str(body["newsletter_issue_id"].as_str().unwrap()).is_ok());
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn publishing_through_the_api_is_idempotent() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::PublishNewsletters])
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let first = app
+        .post_api_newsletters(&token, Some("ci-run-1"), &newsletter_body())
+        .await;
+    let second = app
+        .post_api_newsletters(&token, Some("ci-run-1"), &newsletter_body())
+        .await;
+
+    // Then
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_body_is_rejected_with_a_json_error() {
+    // Given
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::PublishNewsletters])
+        .await;
+    app.post_api_newsletters(&token, Some("ci-run-1"), &newsletter_body())
+        .await;
+
+    // When
+    let mut body = newsletter_body();
+    body["title"] = "Another newsletter title".into();
+    let response = app
+        .post_api_newsletters(&token, Some("ci-run-1"), &body)
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "The idempotency key was already used for a different request."
+    );
+}
+
+#[tokio::test]
+async fn publishing_through_the_api_requires_an_idempotency_key() {
+    // Given
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::PublishNewsletters])
+        .await;
+
+    // When
+    let response = app
+        .post_api_newsletters(&token, None, &newsletter_body())
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "The Idempotency-Key header is missing.");
+}
+
+#[tokio::test]
+async fn invalid_newsletters_are_rejected_with_a_json_error() {
+    // Given
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::PublishNewsletters])
+        .await;
+
+    // When
+    let response = app
+        .post_api_newsletters(
+            &token,
+            Some("key"),
+            &serde_json::json!({
+                "title": "Hi {{ first_name }}",
+                "text_content": "Hello",
+                "html_content": "<p>Hello</p>",
+            }),
+        )
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Unknown merge tags: first_name."));
+}
+
+#[tokio::test]
+async fn issues_are_listed_as_json() {
+    // Given
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(
+            app.test_user.user_id,
+            &[ApiScope::PublishNewsletters, ApiScope::ReadIssues],
+        )
+        .await;
+    app.post_api_newsletters(&token, Some("key"), &newsletter_body())
+        .await;
+
+    // When
+    let response = app.get_api("/issues", &token).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["issues"][0]["title"], "Newsletter title");
+    assert_eq!(body["issues"][0]["status"], "published");
+}
+
+#[tokio::test]
+async fn listing_issues_past_the_last_page_returns_no_issues() {
+    // Given
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::ReadIssues])
+        .await;
+
+    // When
+    let response = app
+        .get_api(&format!("/issues?page={}", i64::MAX), &token)
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["issues"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_as_json() {
+    // Given
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subsriber(&app).await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::ReadSubscribers])
+        .await;
+
+    // When
+    let all = app.get_api("/subscribers", &token).await;
+    let confirmed = app.get_api("/subscribers?status=confirmed", &token).await;
+
+    // Then
+    let all: serde_json::Value = all.json().await.unwrap();
+    assert_eq!(all["total"], 2);
+    let confirmed: serde_json::Value = confirmed.json().await.unwrap();
+    assert_eq!(confirmed["total"], 1);
+    assert_eq!(confirmed["subscribers"][0]["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn listing_subscribers_past_the_last_page_returns_no_subscribers() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::ReadSubscribers])
+        .await;
+
+    // When
+    let response = app
+        .get_api(&format!("/subscribers?page={}", i64::MAX), &token)
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["subscribers"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn tokens_are_created_from_the_admin_area_and_shown_once() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app
+        .post_create_api_token(&[
+            ("name", "CI"),
+            ("scope", "issues:read"),
+            ("scope", "subscribers:read"),
+        ])
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let token = html_page
+        .split("<code>")
+        .nth(1)
+        .and_then(|s| s.split("</code>").next())
+        .unwrap()
+        .to_owned();
+    assert!(token.starts_with("zp_"));
+    assert_eq!(app.get_api("/issues", &token).await.status().as_u16(), 200);
+    assert_eq!(
+        app.get_api("/subscribers", &token).await.status().as_u16(),
+        200
+    );
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("issues:read, subscribers:read"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::ReadIssues])
+        .await;
+    let token_id = token_id(&app, app.test_user.user_id).await;
+
+    // When
+    let response = app.post_revoke_api_token(token_id).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("<p><i>The API token has been revoked.</i></p>"));
+    assert_eq!(app.get_api("/issues", &token).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn viewers_cannot_create_tokens_that_publish() {
+    // Given
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // When
+    let response = app
+        .post_create_api_token(&[("name", "CI"), ("scope", "newsletters:publish")])
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("<p><i>The newsletters:publish scope is not available to you.</i></p>"));
+}
@@ -0,0 +1,256 @@
+use crate::helpers::{create_confirmed_subsriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp, title: &str) {
+    let newsletter_request_body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Hi {{ name }}, here is the news</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletters(&newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn create_unpublished_issues(app: &TestApp) {
+    let draft_request_body = serde_json::json!({
+        "title": "Secret draft",
+        "markdown_content": "Not ready yet",
+    });
+    app.post_create_draft(&draft_request_body).await;
+    let scheduled_request_body = serde_json::json!({
+        "title": "Secret scheduled issue",
+        "markdown_content": "Not sent yet",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "scheduled_for": "2999-01-01T10:00",
+        "timezone": "UTC",
+    });
+    app.post_publish_newsletters(&scheduled_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_in_the_public_archive() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app, "Hello, World!").await;
+    create_unpublished_issues(&app).await;
+    app.post_logout().await;
+
+    // When
+    let html_page = app.get_archive("/archive").await.text().await.unwrap();
+
+    // Then
+    assert!(html_page.contains("Hello, World!"));
+    assert!(html_page.contains("/archive/hello-world-"));
+    assert!(!html_page.contains("Secret"));
+}
+
+#[tokio::test]
+async fn the_public_archive_is_paginated() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..21 {
+        publish_newsletter(&app, &format!("Issue #{:02}", i)).await;
+    }
+    app.post_logout().await;
+
+    // When
+    let first_page = app.get_archive("/archive").await.text().await.unwrap();
+    let second_page = app
+        .get_archive("/archive?page=2")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Then
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page.contains(r#"<a href="/archive?page=2">Older issues</a>"#));
+    assert_eq!(first_page.matches("<li>").count(), 20);
+    assert!(second_page.contains("Page 2 of 2"));
+    assert_eq!(second_page.matches("<li>").count(), 1);
+}
+
+#[tokio::test]
+async fn archived_issues_are_rendered_without_personal_details() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app, "Hello, World!").await;
+    let slug = sqlx::query!(r#"SELECT slug as "slug!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+
+    // When
+    let response = app.get_archive(&format!("/archive/{}", slug)).await;
+
+    // Then
+    assert_eq!(200, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Hello, World!</h1>"));
+    assert!(html_page.contains("<p>Hi reader, here is the news</p>"));
+}
+
+#[tokio::test]
+async fn scripts_in_hand_written_html_are_not_served_from_the_archive() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Hello, World!",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Hi</p><script>alert('pwned')</script><img src=x onerror=alert(1)>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletters(&newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let slug = sqlx::query!(r#"SELECT slug as "slug!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+    app.post_logout().await;
+
+    // When
+    let html_page = app
+        .get_archive(&format!("/archive/{}", slug))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let feed = app.get_archive("/feed.atom").await.text().await.unwrap();
+
+    // Then
+    assert!(html_page.contains("<p>Hi</p>"));
+    assert!(!html_page.contains("<script>"));
+    assert!(!html_page.contains("onerror"));
+    assert!(!feed.contains("script"));
+    assert!(!feed.contains("onerror"));
+}
+
+#[tokio::test]
+async fn unpublished_issues_cannot_be_viewed_online() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_unpublished_issues(&app).await;
+    // Even if they somehow had a slug
+    sqlx::query!("UPDATE newsletter_issues SET slug = newsletter_issue_id::text")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_ids = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+
+    for issue in issue_ids {
+        // When
+        let response = app
+            .get_archive(&format!("/archive/{}", issue.newsletter_issue_id))
+            .await;
+
+        // Then
+        assert_eq!(404, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_published_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app, "Fish & Chips").await;
+    create_unpublished_issues(&app).await;
+
+    // When
+    let response = app.get_archive("/feed.atom").await;
+
+    // Then
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(feed.contains("<title>Fish &amp; Chips</title>"));
+    assert!(feed.contains("&lt;p&gt;Hi reader, here is the news&lt;/p&gt;"));
+    assert!(feed.contains(&format!(
+        r#"<link href="{}/archive/fish-chips-"#,
+        app.base_url
+    )));
+    assert!(!feed.contains("Secret"));
+}
+
+#[tokio::test]
+async fn the_rss_feed_lists_published_issues() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app, "Fish & Chips").await;
+    create_unpublished_issues(&app).await;
+
+    // When
+    let response = app.get_archive("/feed.rss").await;
+
+    // Then
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<rss version="2.0">"#));
+    assert!(feed.contains("<title>Fish &amp; Chips</title>"));
+    assert!(feed.contains(&format!("<link>{}/archive/fish-chips-", app.base_url)));
+    assert!(!feed.contains("Secret"));
+}
+
+#[tokio::test]
+async fn delivered_emails_link_to_the_online_version() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    publish_newsletter(&app, "Hello, World!").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_view_online_links(&email_request);
+    assert_eq!(links.html, links.plain_text);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Hello, World!</h1>"));
+}
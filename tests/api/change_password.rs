@@ -135,3 +135,57 @@ async fn should_be_able_to_change_password() {
     let response = app.post_login(&login_body).await;
     assert_is_redirect_to(&response, admin_dashboard_uri);
 }
+
+#[tokio::test]
+async fn the_password_is_left_unchanged_if_revoking_the_other_sessions_fails() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let password_hash = || async {
+        sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            app.test_user.user_id
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash
+    };
+    let old_password_hash = password_hash().await;
+    // Sabotage the database so that revoking the other sessions fails
+    sqlx::query!(
+        r#"
+        CREATE FUNCTION refuse_session_deletion() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'Sessions cannot be deleted';
+        END;
+        $$ LANGUAGE plpgsql
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        CREATE TRIGGER refuse_session_deletion
+        BEFORE DELETE ON user_sessions
+        FOR EACH STATEMENT EXECUTE FUNCTION refuse_session_deletion()
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // When
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": "a-new-password",
+            "new_password_check": "a-new-password",
+        }))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 500);
+    assert_eq!(old_password_hash, password_hash().await);
+}
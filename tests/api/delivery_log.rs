@@ -0,0 +1,210 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subsriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletters(&newsletter_request_body).await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn delivery_report(app: &TestApp, issue_id: Uuid) -> serde_json::Value {
+    app.get_issue_delivery_report(issue_id)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn deliveries_are_reported_as_queued_until_they_are_sent() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+        })))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let issue_id = publish_newsletter(&app).await;
+
+    // Then
+    let report = delivery_report(&app, issue_id).await;
+    assert_eq!(report["queued"], 2);
+    assert_eq!(report["sent"], 0);
+
+    app.dispatch_all_pending_emails().await;
+    let report = delivery_report(&app, issue_id).await;
+    assert_eq!(report["queued"], 0);
+    assert_eq!(report["sent"], 2);
+    assert_eq!(report["failed"], 0);
+    let message_ids = sqlx::query!("SELECT message_id FROM issue_delivery_log")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(message_ids
+        .iter()
+        .all(|r| r.message_id.as_deref() == Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")));
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_listed_in_the_report() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let max_attempts = app.issue_delivery.max_retries as u64 + 1;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(max_attempts)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let issue_id = publish_newsletter(&app).await;
+    for _ in 0..max_attempts {
+        app.skip_retry_backoff().await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Then
+    let report = delivery_report(&app, issue_id).await;
+    assert_eq!(report["queued"], 0);
+    assert_eq!(report["failed"], 1);
+    let failure = &report["failures"][0];
+    assert_eq!(failure["n_attempts"], max_attempts);
+    assert!(failure["error"].as_str().unwrap().contains("500"));
+    let html_page = app.get_issue_details_html(issue_id, "").await;
+    assert!(html_page.contains("<li>Failed: 1</li>"));
+}
+
+#[tokio::test]
+async fn invalid_and_unsubscribed_recipients_are_reported_as_skipped() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let issue_id = publish_newsletter(&app).await;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET subscriber_email = 'not-an-email'
+        WHERE subscriber_email = (SELECT MIN(subscriber_email) FROM issue_delivery_queue)
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let report = delivery_report(&app, issue_id).await;
+    assert_eq!(report["skipped"], 2);
+    let outcomes = sqlx::query!("SELECT outcome FROM issue_delivery_log ORDER BY outcome")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(outcomes[0].outcome, "skipped_invalid_email");
+    assert_eq!(outcomes[1].outcome, "skipped_unsubscribed");
+}
+
+#[tokio::test]
+async fn the_delivery_log_is_paginated() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            n_attempts,
+            enqueued_at,
+            completed_at
+        )
+        SELECT $1, 'subscriber' || n || '@example.com', 'sent', 1, now(), now()
+        FROM generate_series(1, 51) AS n
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // When
+    let first_page = app.get_issue_details_html(issue_id, "").await;
+    let second_page = app.get_issue_details_html(issue_id, "?page=2").await;
+
+    // Then
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page.contains(&format!(
+        r#"href="/admin/newsletters/issues/{}?page=2">Next</a>"#,
+        issue_id
+    )));
+    assert_eq!(first_page.matches("@example.com</td>").count(), 50);
+    assert!(second_page.contains("Page 2 of 2"));
+    assert_eq!(second_page.matches("@example.com</td>").count(), 1);
+}
+
+#[tokio::test]
+async fn unknown_issues_have_no_report() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app.get_issue_delivery_report(Uuid::new_v4()).await;
+
+    // Then
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn should_be_logged_in_to_see_delivery_reports() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_issue_delivery_report(Uuid::new_v4()).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
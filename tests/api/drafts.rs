@@ -0,0 +1,292 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subsriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn draft_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+    })
+}
+
+/// Creates a draft and returns the URL of its edit page.
+async fn create_draft(app: &TestApp) -> String {
+    let response = app.post_create_draft(&draft_request_body()).await;
+    assert_eq!(303, response.status().as_u16());
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert!(location.starts_with("/admin/newsletters/drafts/"));
+    location
+}
+
+async fn count_queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn saving_a_draft_does_not_send_anything() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let draft_url = create_draft(&app).await;
+
+    // Then
+    let html_page = app.get_draft_html(&draft_url).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("Newsletter title"));
+    assert!(html_page.contains(&draft_url));
+    assert_eq!(count_queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_url = create_draft(&app).await;
+
+    // When
+    let response = app
+        .post_draft(
+            &draft_url,
+            &serde_json::json!({
+                "title": "A better title",
+                "text_content": "Line one\nLine two",
+                "html_content": "<h1>Hello</h1>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_url);
+
+    // Then
+    let html_page = app.get_draft_html(&draft_url).await;
+    assert!(html_page.contains(r#"value="A better title""#));
+    assert!(html_page.contains("Line one\nLine two</textarea>"));
+    assert!(html_page.contains("&lt;h1&gt;Hello&lt;/h1&gt;</textarea>"));
+}
+
+#[tokio::test]
+async fn markdown_drafts_are_edited_as_markdown() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_url = create_draft(&app).await;
+
+    // When
+    app.post_draft(
+        &draft_url,
+        &serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "Some **bold** news",
+        }),
+    )
+    .await;
+
+    // Then
+    let html_page = app.get_draft_html(&draft_url).await;
+    assert!(html_page
+        .contains(r#"name="markdown_content" rows="20" cols="80">Some **bold** news</textarea>"#));
+    assert!(!html_page.contains(r#"name="html_content""#));
+    let html_page = app.get_draft_html(&format!("{}/preview", draft_url)).await;
+    assert!(html_page.contains("&lt;strong&gt;bold&lt;/strong&gt;"));
+    assert!(html_page.contains("<pre>Some bold news</pre>"));
+}
+
+#[tokio::test]
+async fn preview_renders_both_versions_of_a_draft() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_url = create_draft(&app).await;
+
+    // When
+    let html_page = app.get_draft_html(&format!("{}/preview", draft_url)).await;
+
+    // Then
+    assert!(html_page.contains("&lt;p&gt;Newsletter body as html&lt;/p&gt;"));
+    assert!(html_page.contains("<pre>Newsletter body as plain text</pre>"));
+}
+
+#[tokio::test]
+async fn test_emails_are_only_sent_to_the_given_address() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_url = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_draft(
+            &format!("{}/test", draft_url),
+            &serde_json::json!({"email": "admin@example.com"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_url);
+
+    // Then
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "admin@example.com");
+    assert_eq!(body["Subject"], "[Test] Newsletter title");
+    let html_page = app.get_draft_html(&draft_url).await;
+    assert!(html_page.contains("<p><i>A test email has been sent to admin@example.com.</i></p>"));
+    assert_eq!(count_queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn test_emails_render_merge_tags_with_sample_values() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "News for {{ name }}",
+            "text_content": "Hi {{ name }}, sent to {{ email }}",
+            "html_content": "<p>Hi {{ name }}, <a href=\"{{ unsubscribe_url }}\">unsubscribe</a></p>",
+        }))
+        .await;
+    let draft_url = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    app.post_draft(
+        &format!("{}/test", draft_url),
+        &serde_json::json!({"email": "admin@example.com"}),
+    )
+    .await;
+
+    // Then
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "[Test] News for reader");
+    assert_eq!(body["TextBody"], "Hi reader, sent to admin@example.com");
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(!html_body.contains("{{"));
+    assert!(html_body.contains(&format!(r#"<a href="{}">"#, app.base_url)));
+}
+
+#[tokio::test]
+async fn test_emails_require_a_valid_address() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_url = create_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_draft(
+            &format!("{}/test", draft_url),
+            &serde_json::json!({"email": "not-an-email"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_url);
+
+    // Then
+    let html_page = app.get_draft_html(&draft_url).await;
+    assert!(html_page.contains("<p><i>The test email address is not valid.</i></p>"));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_to_confirmed_subscribers() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_url = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_draft(
+            &format!("{}/publish", draft_url),
+            &serde_json::json!({
+                "idempotency_key": Uuid::new_v4().to_string(),
+                "scheduled_for": "",
+                "timezone": "UTC",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted \
+        - emails will go out shortly.</i></p>"
+    ));
+    // A published issue is no longer a draft
+    let response = app.get_draft(&draft_url).await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.post_create_draft(&draft_request_body()).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
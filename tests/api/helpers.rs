@@ -1,12 +1,27 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use chrono::Utc;
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
-use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::authentication::{create_api_token, ApiScope, TotpSecret};
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, IdempotencySettings, IssueDeliverySettings, Settings,
+};
+use zero2prod::email_client::EmailSender;
+use zero2prod::issue_delivery_worker::{
+    run_worker_until_stopped, try_execute_task, ExecutionOutcome,
+};
+use zero2prod::issue_scheduler::try_publish_scheduled_issue;
+use zero2prod::shutdown::{shutdown_channel, Shutdown, ShutdownTrigger};
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -14,10 +29,18 @@ pub struct TestApp {
     pub address: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailSender>,
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub issue_delivery: IssueDeliverySettings,
+    pub idempotency: IdempotencySettings,
+    /// Stops the application and the workers spawned with `spawn_worker`.
+    pub shutdown_trigger: ShutdownTrigger,
+    shutdown: Shutdown,
+    configuration: Settings,
 }
 
 pub struct ConfirmationsLinks {
@@ -25,21 +48,36 @@ pub struct ConfirmationsLinks {
     pub plain_text: reqwest::Url,
 }
 
+pub struct UnsubscribeLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+    pub header: reqwest::Url,
+}
+
 pub struct TestUser {
-    user_id: Uuid,
+    pub user_id: Uuid,
     pub username: String,
+    pub email: String,
     pub password: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
     fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+
+    pub fn generate_with_role(role: &'static str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
+            email: SafeEmail().fake(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
-    async fn store(&self, pool: &PgPool) {
+
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
             Algorithm::Argon2id,
@@ -50,10 +88,13 @@ impl TestUser {
         .unwrap()
         .to_string();
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, email, password_hash, role) \
+            VALUES ($1, $2, $3, $4, $5)",
             self.user_id,
             self.username,
-            password_hash
+            self.email,
+            password_hash,
+            self.role
         )
         .execute(pool)
         .await
@@ -72,7 +113,7 @@ impl TestUser {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -85,7 +126,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .form(body)
             .send()
             .await
@@ -94,30 +135,85 @@ impl TestApp {
 
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationsLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
-        let expected_host = "127.0.0.1";
-        let get_link = |s: &str| {
-            let links: Vec<_> = linkify::LinkFinder::new()
-                .links(s)
-                .filter(|l| *l.kind() == linkify::LinkKind::Url)
-                .collect();
-            assert_eq!(links.len(), 1);
-            let raw_link = links[0].as_str().to_owned();
-            let mut confirmation_link = reqwest::Url::parse(&raw_link).unwrap();
-            assert_eq!(expected_host, confirmation_link.host_str().unwrap());
-            confirmation_link.set_port(Some(self.port)).unwrap();
-            confirmation_link
-        };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = self.get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = self.get_link(body["TextBody"].as_str().unwrap());
+        ConfirmationsLinks { html, plain_text }
+    }
+
+    pub fn get_unsubscribe_links(&self, email_request: &wiremock::Request) -> UnsubscribeLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = self.get_link_to(
+            body["HtmlBody"].as_str().unwrap(),
+            "/subscriptions/unsubscribe",
+        );
+        let plain_text = self.get_link_to(
+            body["TextBody"].as_str().unwrap(),
+            "/subscriptions/unsubscribe",
+        );
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap()["Value"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+        let header = self.get_link(&header);
+        UnsubscribeLinks {
+            html,
+            plain_text,
+            header,
+        }
+    }
+
+    pub fn get_view_online_links(&self, email_request: &wiremock::Request) -> ConfirmationsLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = self.get_link_to(body["HtmlBody"].as_str().unwrap(), "/archive/");
+        let plain_text = self.get_link_to(body["TextBody"].as_str().unwrap(), "/archive/");
+        ConfirmationsLinks { html, plain_text }
+    }
+
+    pub fn get_password_reset_links(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> ConfirmationsLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let html = self.get_link_to(body["HtmlBody"].as_str().unwrap(), "/password-reset/");
+        let plain_text = self.get_link_to(body["TextBody"].as_str().unwrap(), "/password-reset/");
         ConfirmationsLinks { html, plain_text }
     }
 
+    fn get_link(&self, s: &str) -> reqwest::Url {
+        self.get_link_to(s, "")
+    }
+
+    /// Extracts the only link in `s` whose path starts with `path`.
+    fn get_link_to(&self, s: &str, path: &str) -> reqwest::Url {
+        let expected_host = "127.0.0.1";
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(s)
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .filter(|l| {
+                reqwest::Url::parse(l.as_str())
+                    .map(|url| url.path().starts_with(path))
+                    .unwrap_or(false)
+            })
+            .collect();
+        assert_eq!(links.len(), 1);
+        let raw_link = links[0].as_str().to_owned();
+        let mut link = reqwest::Url::parse(&raw_link).unwrap();
+        assert_eq!(expected_host, link.host_str().unwrap());
+        link.set_port(Some(self.port)).unwrap();
+        link
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -126,7 +222,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -135,9 +231,44 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password-reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_password_reset_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/password-reset", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -149,7 +280,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -164,24 +295,216 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_create_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `action` is one of `role`, `disable` or `enable`.
+    pub async fn post_user_action<Body>(
+        &self,
+        user_id: Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, user_id, action
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `action` is one of `setup`, `confirm` or `disable`.
+    pub async fn post_two_factor<Body>(&self, action: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/two-factor/{}", &self.address, action))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Enables two-factor authentication for the logged-in user, returning
+    /// their secret and recovery codes.
+    pub async fn enable_two_factor(&self, user_id: Uuid) -> (TotpSecret, Vec<String>) {
+        self.post_two_factor("setup", &serde_json::json!({})).await;
+        let secret = sqlx::query!(
+            r#"SELECT totp_secret as "totp_secret!" FROM users WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .unwrap()
+        .totp_secret;
+        let secret = TotpSecret::parse(&secret).unwrap();
+        let html_page = self
+            .post_two_factor(
+                "confirm",
+                &serde_json::json!({ "code": secret.code_at(Utc::now().timestamp()) }),
+            )
+            .await
+            .text()
+            .await
+            .unwrap();
+        let recovery_codes = html_page
+            .split("<li><code>")
+            .skip(1)
+            .map(|s| s.split("</code>").next().unwrap().to_owned())
+            .collect();
+        (secret, recovery_codes)
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke", &self.address))
+            .form(&serde_json::json!({ "session_id": session_id }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke-others", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_token(&self, body: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.address))
+            .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_revoke_api_token(&self, token_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/api-tokens/revoke", &self.address))
+            .form(&serde_json::json!({ "token_id": token_id }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Creates a token straight in the database, as if from the dashboard.
+    pub async fn create_api_token(&self, user_id: Uuid, scopes: &[ApiScope]) -> String {
+        create_api_token(&self.db_pool, user_id, "Test token", scopes)
+            .await
+            .unwrap()
+            .expose_secret()
+            .to_owned()
+    }
+
+    pub async fn get_api(&self, path: &str, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_api_newsletters<Body>(
+        &self,
+        token: &str,
+        idempotency_key: Option<&str>,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut request = self
+            .api_client
+            .post(format!("{}/api/v1/newsletters", &self.address))
+            .bearer_auth(token)
+            .json(body);
+        if let Some(idempotency_key) = idempotency_key {
+            request = request.header("Idempotency-Key", idempotency_key);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
     pub async fn get_publish_newsletters(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -191,12 +514,248 @@ impl TestApp {
         self.get_publish_newsletters().await.text().await.unwrap()
     }
 
-    pub async fn dispatch_all_pending_emails(&self) {
+    pub async fn get_delivery_failures_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/failures", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_requeue_delivery_failure<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/failures/requeue",
+                &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_draft(&self, draft_url: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, draft_url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_html(&self, draft_url: &str) -> String {
+        self.get_draft(draft_url).await.text().await.unwrap()
+    }
+
+    pub async fn post_draft<Body>(&self, draft_url: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}{}", &self.address, draft_url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issues_html(&self, query: &str) -> String {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/issues{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_issue_action(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/issues/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issue_details_html(&self, issue_id: Uuid, query: &str) -> String {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/issues/{}{}",
+                &self.address, issue_id, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_issue_delivery_report(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/issues/{}/delivery",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_scheduled_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/scheduled", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reschedule_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/scheduled/reschedule",
+                &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel_scheduled_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/scheduled/cancel",
+                &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn publish_due_scheduled_issues(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+                try_publish_scheduled_issue(&self.db_pool).await.unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Make every delivery task waiting for a retry eligible right away.
+    pub async fn skip_retry_backoff(&self) {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&self.db_pool)
+            .await
+            .unwrap();
+    }
+
+    /// Runs the delivery workers in the background, as the application does.
+    pub fn spawn_worker(&self) -> JoinHandle<Result<(), anyhow::Error>> {
+        tokio::spawn(run_worker_until_stopped(
+            self.configuration.clone(),
+            self.shutdown.clone(),
+        ))
+    }
+
+    /// Waits (up to a point) for the delivery queue to be emptied by a worker.
+    /// For emails sent in the background: waits until the email server has
+    /// received `n` requests, and returns them.
+    pub async fn wait_for_emails(&self, n: usize) -> Vec<wiremock::Request> {
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= n {
+                return requests;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                panic!("Expected {} emails, got {}.", n, requests.len());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    }
+
+    pub async fn wait_for_empty_queue(&self, timeout: std::time::Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while tokio::time::Instant::now() < deadline {
+            let n_queued = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+                .fetch_one(&self.db_pool)
+                .await
+                .unwrap()
+                .count;
+            if n_queued == 0 {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.base_url,
+                &self.hmac_secret,
+                &self.issue_delivery,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -216,7 +775,30 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+/// Each test app pretends to sit behind a proxy that forwards requests from
+/// its own client IP, so that failed logins in one test can't lock another out.
+pub fn test_client(client_ip: &str) -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("X-Forwarded-For", client_ip.parse().unwrap());
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
+pub fn random_ip() -> String {
+    let [a, b, c]: [u8; 3] = rand::random();
+    format!("10.{}.{}.{}", a, b, c)
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, with a chance to tweak the configuration first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
     let configuration = {
@@ -224,6 +806,9 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        // Lets `test_client` pick the client IP the application sees
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        configure(&mut c);
         c
     };
     configure_database(&configuration.database).await;
@@ -234,12 +819,9 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
     let port = application.port();
     let address = format!("http://127.0.0.1:{}", port);
-    tokio::spawn(application.run_until_stopped());
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let (shutdown_trigger, shutdown) = shutdown_channel();
+    tokio::spawn(application.run_until_stopped(shutdown.clone()));
+    let client = test_client(&random_ip());
 
     let test_app = TestApp {
         address,
@@ -248,7 +830,14 @@ pub async fn spawn_app() -> TestApp {
         port,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().rate_limited_client(),
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        issue_delivery: configuration.issue_delivery.clone(),
+        idempotency: configuration.idempotency.clone(),
+        shutdown_trigger,
+        shutdown,
+        configuration,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -273,6 +862,48 @@ async fn configure_database(config: &DatabaseSettings) {
         .expect("Failed to migrate the database");
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationsLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email,
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subsriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     let redirect_status = 303;
     assert_eq!(redirect_status, response.status());
@@ -0,0 +1,420 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subsriber, spawn_app, spawn_app_with, TestApp,
+};
+use sqlx::{Postgres, Transaction};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::IdempotencySettings;
+use zero2prod::idempotency::{
+    purge_expired_keys, save_response, try_processing, IdempotencyKey, NextAction,
+    RequestFingerprint,
+};
+use zero2prod::utils::see_other;
+
+/// Pretends the key was first used two days ago, past the TTL.
+async fn age_key(app: &TestApp, idempotency_key: &str) {
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET created_at = now() - interval '2 days'
+        WHERE idempotency_key = $1
+        "#,
+        idempotency_key
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn store_key(app: &TestApp, idempotency_key: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Starts processing a request with the key, as a concurrent request would.
+async fn start_processing(app: &TestApp, idempotency_key: &str) -> Transaction<'static, Postgres> {
+    let key: IdempotencyKey = idempotency_key.to_owned().try_into().unwrap();
+    // What the publishing form fingerprints out of `newsletter_form`.
+    let fingerprint = RequestFingerprint::of(&serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": null,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "scheduled_for": null,
+        "timezone": null,
+    }))
+    .unwrap();
+    match try_processing(
+        &app.db_pool,
+        &key,
+        &fingerprint,
+        app.test_user.user_id,
+        &app.idempotency,
+    )
+    .await
+    .unwrap()
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        _ => panic!("The key was expected to be fresh"),
+    }
+}
+
+fn newsletter_form(idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key,
+    })
+}
+
+async fn stored_keys(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT idempotency_key FROM idempotency ORDER BY idempotency_key")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.idempotency_key)
+        .collect()
+}
+
+#[tokio::test]
+async fn an_expired_key_is_processed_again() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let newsletter_request_body = newsletter_form(&idempotency_key);
+    app.post_publish_newsletters(&newsletter_request_body).await;
+    age_key(&app, &idempotency_key).await;
+
+    // When
+    let response = app.post_publish_newsletters(&newsletter_request_body).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn purging_deletes_expired_keys_only() {
+    // Given
+    let app = spawn_app().await;
+    store_key(&app, "expired").await;
+    store_key(&app, "fresh").await;
+    age_key(&app, "expired").await;
+
+    // When
+    let n_purged_keys = purge_expired_keys(&app.db_pool, &app.idempotency)
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(n_purged_keys, 1);
+    assert_eq!(stored_keys(&app).await, vec!["fresh".to_string()]);
+}
+
+#[tokio::test]
+async fn purging_goes_through_every_batch() {
+    // Given
+    let app = spawn_app().await;
+    for i in 0..5 {
+        let idempotency_key = format!("key-{i}");
+        store_key(&app, &idempotency_key).await;
+        age_key(&app, &idempotency_key).await;
+    }
+    let settings = IdempotencySettings {
+        cleanup_batch_size: 2,
+        ..app.idempotency.clone()
+    };
+
+    // When
+    let n_purged_keys = purge_expired_keys(&app.db_pool, &settings).await.unwrap();
+
+    // Then
+    assert_eq!(n_purged_keys, 5);
+    assert!(stored_keys(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_concurrent_request_waits_and_replays_the_response_of_the_first_one() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let transaction = start_processing(&app, &idempotency_key).await;
+    let key: IdempotencyKey = idempotency_key.clone().try_into().unwrap();
+    let in_flight = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let response = see_other("/from-the-first-request");
+        save_response(transaction, &key, app.test_user.user_id, response)
+            .await
+            .unwrap();
+    };
+
+    // When
+    let body = newsletter_form(&idempotency_key);
+    let (response, _) = tokio::join!(app.post_publish_newsletters(&body), in_flight);
+
+    // Then
+    assert_is_redirect_to(&response, "/from-the-first-request");
+}
+
+#[tokio::test]
+async fn a_concurrent_request_gets_a_409_once_it_has_waited_long_enough() {
+    // Given
+    let app = spawn_app_with(|c| c.idempotency.concurrent_request_wait_milliseconds = 300).await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let _transaction = start_processing(&app, &idempotency_key).await;
+
+    // When
+    let start = Instant::now();
+    let response = app
+        .post_publish_newsletters(&newsletter_form(&idempotency_key))
+        .await;
+
+    // Then
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(
+        response.headers()["Retry-After"],
+        app.idempotency.retry_after_seconds.to_string().as_str()
+    );
+}
+
+#[tokio::test]
+async fn a_concurrent_request_gets_a_409_right_away_when_waiting_is_disabled() {
+    // Given
+    let app = spawn_app_with(|c| c.idempotency.concurrent_request_wait_milliseconds = 0).await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let _transaction = start_processing(&app, &idempotency_key).await;
+
+    // When
+    let start = Instant::now();
+    let response = app
+        .post_publish_newsletters(&newsletter_form(&idempotency_key))
+        .await;
+
+    // Then
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(response.status().as_u16(), 409);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn the_key_is_released_once_the_first_request_gives_up() {
+    // Given
+    let app = spawn_app_with(|c| c.idempotency.concurrent_request_wait_milliseconds = 0).await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let transaction = start_processing(&app, &idempotency_key).await;
+    transaction.rollback().await.unwrap();
+
+    // When
+    let response = app
+        .post_publish_newsletters(&newsletter_form(&idempotency_key))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_newsletter_is_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_publish_newsletters(&newsletter_form(&idempotency_key))
+        .await;
+
+    // When
+    let mut body = newsletter_form(&idempotency_key);
+    body["title"] = "Another newsletter title".into();
+    let response = app.post_publish_newsletters(&body).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 422);
+    let n_issues = sqlx::query!("SELECT COUNT(*) as \"n!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn an_expired_key_can_be_reused_for_a_different_newsletter() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_publish_newsletters(&newsletter_form(&idempotency_key))
+        .await;
+    age_key(&app, &idempotency_key).await;
+
+    // When
+    let mut body = newsletter_form(&idempotency_key);
+    body["title"] = "Another newsletter title".into();
+    let response = app.post_publish_newsletters(&body).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn keys_saved_without_a_fingerprint_are_still_replayed() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_publish_newsletters(&newsletter_form(&idempotency_key))
+        .await;
+    sqlx::query!("UPDATE idempotency SET request_fingerprint = NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let mut body = newsletter_form(&idempotency_key);
+    body["title"] = "Another newsletter title".into();
+    let response = app.post_publish_newsletters(&body).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+fn create_user_form(username: &str, idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "username": username,
+        "password": "a-long-password",
+        "password_check": "a-long-password",
+        "role": "editor",
+        "idempotency_key": idempotency_key,
+    })
+}
+
+#[tokio::test]
+async fn retrying_an_admin_form_replays_the_first_response() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let body = create_user_form("ursula", &idempotency_key);
+    app.post_create_user(&body).await;
+    app.get_users_html().await;
+
+    // When
+    let response = app.post_create_user(&body).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(!html_page.contains("already taken"));
+}
+
+#[tokio::test]
+async fn the_key_can_be_sent_as_a_header_to_admin_forms() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let send = || {
+        app.api_client
+            .post(format!("{}/admin/users", &app.address))
+            .header("Idempotency-Key", "create-ursula")
+            .form(&serde_json::json!({
+                "username": "ursula",
+                "password": "a-long-password",
+                "password_check": "a-long-password",
+                "role": "editor",
+            }))
+            .send()
+    };
+    send().await.unwrap();
+    app.get_users_html().await;
+
+    // When
+    let response = send().await.unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(!app.get_users_html().await.contains("already taken"));
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_admin_form_is_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_create_user(&create_user_form("ursula", &idempotency_key))
+        .await;
+
+    // When
+    let response = app
+        .post_create_user(&create_user_form("victor", &idempotency_key))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 422);
+    assert!(!app.get_users_html().await.contains("victor"));
+}
+
+#[tokio::test]
+async fn retrying_a_password_change_does_not_check_the_old_password_again() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_change_password(&body).await;
+    app.get_change_password_html().await;
+
+    // When
+    let response = app.post_change_password(&body).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(!html_page.contains("The current password is incorrect."));
+}
+
+#[tokio::test]
+async fn admin_forms_carry_an_idempotency_key() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    let password_page = app.get_change_password_html().await;
+    let users_page = app.get_users_html().await;
+
+    // Then
+    assert!(password_page.contains(r#"name="idempotency_key""#));
+    assert!(users_page.contains(r#"name="idempotency_key""#));
+}
@@ -0,0 +1,271 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subsriber, spawn_app, spawn_app_with, TestApp,
+};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletters(&newsletter_request_body).await;
+}
+
+#[tokio::test]
+async fn a_failed_delivery_is_retried_later_instead_of_being_dropped() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let task = sqlx::query!("SELECT n_retries, execute_after FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed delivery should still be queued.");
+    assert_eq!(1, task.n_retries);
+    assert!(task.execute_after > chrono::Utc::now());
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.skip_retry_backoff().await;
+    app.dispatch_all_pending_emails().await;
+    let n_queued = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(0, n_queued);
+}
+
+#[tokio::test]
+async fn a_delivery_is_dead_lettered_once_the_retry_limit_is_reached() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let max_attempts = app.issue_delivery.max_retries as u64 + 1;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(max_attempts)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    publish_newsletter(&app).await;
+    for _ in 0..max_attempts {
+        app.skip_retry_backoff().await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Then
+    let n_queued = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(0, n_queued);
+    let failure = sqlx::query!("SELECT n_retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery should have been dead-lettered.");
+    assert_eq!(app.issue_delivery.max_retries, failure.n_retries);
+    assert!(failure.last_error.contains("500"));
+
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("1 deliveries gave up after too many retries."));
+}
+
+#[tokio::test]
+async fn a_dead_lettered_delivery_can_be_requeued_by_an_admin() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let max_attempts = app.issue_delivery.max_retries as u64 + 1;
+
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(max_attempts)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    for _ in 0..max_attempts {
+        app.skip_retry_backoff().await;
+        app.dispatch_all_pending_emails().await;
+    }
+    drop(mock_guard);
+    let failure =
+        sqlx::query!("SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_failures")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_requeue_delivery_failure(&serde_json::json!({
+            "newsletter_issue_id": failure.newsletter_issue_id,
+            "subscriber_email": failure.subscriber_email,
+        }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters/failures");
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("<p><i>The delivery has been requeued.</i></p>"));
+    assert!(html_page.contains("0 deliveries gave up after too many retries."));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn should_be_logged_in_to_see_delivery_failures() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/admin/newsletters/failures", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_idle_worker_is_woken_up_as_soon_as_an_issue_is_published() {
+    // Given
+    let app = spawn_app_with(|c| c.issue_delivery.poll_interval_milliseconds = 60_000).await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.spawn_worker();
+    // Let the worker find the queue empty and go idle.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // When
+    publish_newsletter(&app).await;
+
+    // Then
+    assert!(app.wait_for_empty_queue(Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn an_idle_worker_is_woken_up_when_a_retry_is_due() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.poll_interval_milliseconds = 60_000;
+        c.issue_delivery.base_backoff_milliseconds = 500;
+    })
+    .await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.spawn_worker();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // When
+    publish_newsletter(&app).await;
+
+    // Then
+    assert!(app.wait_for_empty_queue(Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn workers_send_emails_concurrently() {
+    // Given
+    let app = spawn_app_with(|c| c.issue_delivery.n_workers = 4).await;
+    for _ in 0..4 {
+        create_confirmed_subsriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+    app.spawn_worker();
+
+    // When
+    publish_newsletter(&app).await;
+
+    // Then
+    // One at a time, sending the four emails would take four seconds.
+    assert!(app.wait_for_empty_queue(Duration::from_millis(2500)).await);
+}
+
+#[tokio::test]
+async fn a_rate_limited_delivery_is_postponed_without_counting_as_a_retry() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let task = sqlx::query!("SELECT n_retries, execute_after FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The rate limited delivery should still be queued.");
+    assert_eq!(0, task.n_retries);
+    assert!(task.execute_after > chrono::Utc::now() + chrono::Duration::seconds(50));
+}
@@ -0,0 +1,195 @@
+use crate::helpers::{
+    assert_is_redirect_to, random_ip, spawn_app, spawn_app_with, test_client, TestApp,
+};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": "wrong-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+async fn login_with_correct_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    // Given
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // When
+    let response = login_with_correct_password(&app).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(
+        html_page.contains("<p><i>Too many failed login attempts. Please try again later.</i></p>")
+    );
+}
+
+#[tokio::test]
+async fn a_locked_out_username_is_locked_out_from_every_ip() {
+    // Given
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let other_client = test_client(&random_ip());
+
+    // When
+    let response = other_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_ip_is_locked_out_after_too_many_failures_across_usernames() {
+    // Given
+    let app = spawn_app().await;
+    for _ in 0..20 {
+        fail_login(&app, &Uuid::new_v4().to_string()).await;
+    }
+
+    // When
+    let response = login_with_correct_password(&app).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    // The user can still log in from elsewhere.
+    let other_client = test_client(&random_ip());
+    let response = other_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+/// Fails a login with a random username, from a client sending the given
+/// `X-Forwarded-For` header.
+async fn fail_login_forwarded_for(app: &TestApp, forwarded_for: &str) {
+    let response = test_client(forwarded_for)
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": "wrong-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn spoofed_forwarded_headers_do_not_reset_the_ip_counter() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec![];
+        c.application.login_throttling.max_failures_per_ip = 3;
+    })
+    .await;
+    for _ in 0..3 {
+        fail_login_forwarded_for(&app, &random_ip()).await;
+    }
+
+    // When
+    let response = test_client(&random_ip())
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_the_addresses_added_by_trusted_proxies_are_used() {
+    // Given
+    let app = spawn_app_with(|c| c.application.login_throttling.max_failures_per_ip = 3).await;
+    let client_ip = random_ip();
+    for _ in 0..3 {
+        fail_login_forwarded_for(&app, &format!("{}, {}", random_ip(), client_ip)).await;
+    }
+
+    // When
+    let response = test_client(&format!("{}, {}", random_ip(), client_ip))
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn each_failure_slows_down_the_next_attempt() {
+    // Given
+    let app = spawn_app().await;
+    fail_login(&app, &app.test_user.username).await;
+    fail_login(&app, &app.test_user.username).await;
+
+    // When
+    let start = Instant::now();
+    login_with_correct_password(&app).await;
+
+    // Then
+    assert!(start.elapsed() >= Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failures_of_the_username() {
+    // Given
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = login_with_correct_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // When
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = login_with_correct_password(&app).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -1,8 +1,23 @@
 mod admin_dashboard;
+mod api_v1;
+mod archive;
 mod change_password;
+mod delivery_log;
+mod drafts;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod issue_delivery;
 mod login;
+mod login_throttling;
 mod newsletter;
+mod newsletter_issues;
+mod password_reset;
+mod scheduled_newsletters;
+mod sessions;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod two_factor;
+mod users;
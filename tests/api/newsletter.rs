@@ -1,53 +1,10 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationsLinks, TestApp};
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subsriber, create_unconfirmed_subscriber, spawn_app,
+};
 use std::time::Duration;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationsLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email,
-    }))
-    .unwrap();
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(email_request)
-}
-
-async fn create_confirmed_subsriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 async fn should_not_send_newsletter_to_unconfirmed_subscribers() {
     // Given
@@ -117,6 +74,159 @@ async fn should_send_newsletter_to_confirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn should_render_markdown_newsletters_to_html_and_plain_text() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": "# Hello\n\nRead [our blog](https://example.com).",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_publish_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<h1>Hello</h1>\n<p>Read <a href=\"https://example.com\""));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hello\n\nRead our blog (https://example.com)"));
+    let saved = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved.markdown_content.as_deref(),
+        Some("# Hello\n\nRead [our blog](https://example.com).")
+    );
+}
+
+#[tokio::test]
+async fn should_personalize_newsletters_with_merge_tags() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET name = 'Ursula <Le Guin>'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{ name }}",
+        "text_content": "Hi {{ name }}, you joined on {{ subscribed_at }}.",
+        "html_content": "<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_publish_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let subscribed_at = sqlx::query!("SELECT subscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscribed_at
+        .format("%Y-%m-%d")
+        .to_string();
+    assert_eq!(body["Subject"], "News for Ursula <Le Guin>");
+    assert!(body["TextBody"].as_str().unwrap().starts_with(&format!(
+        "Hi Ursula <Le Guin>, you joined on {}.",
+        subscribed_at
+    )));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<p>Hi Ursula &lt;Le Guin&gt;</p>"));
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    assert!(html_body.contains(&format!(
+        r#"<a href="{}/subscriptions/unsubscribe?subscriber_id={}&amp;token="#,
+        app.base_url, subscriber_id
+    )));
+}
+
+#[tokio::test]
+async fn should_reject_newsletters_with_unknown_merge_tags() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{ first_name }}",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    // When
+    let response = app.post_publish_newsletters(&newsletter_request_body).await;
+
+    // Then
+    assert_eq!(400, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("first_name"));
+}
+
+#[tokio::test]
+async fn should_reject_newsletters_without_a_body() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": "",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+
+    // When
+    let response = app.post_publish_newsletters(&newsletter_request_body).await;
+
+    // Then
+    assert_eq!(400, response.status().as_u16());
+}
+
 #[tokio::test]
 async fn should_be_logged_in_to_see_the_newsletter_form() {
     let expected_redirect = "/login";
@@ -0,0 +1,219 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subsriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp, title: &str) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": title,
+        "markdown_content": "Newsletter body",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletters(&newsletter_request_body).await;
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+async fn set_published_at(app: &TestApp, issue_id: Uuid, published_at: &str) {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = $2::text::timestamptz
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        published_at
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn issues_are_listed_by_publish_date() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let older = publish_newsletter(&app, "Older issue").await;
+    let newer = publish_newsletter(&app, "Newer issue").await;
+    set_published_at(&app, older, "2023-01-01T10:00:00Z").await;
+    set_published_at(&app, newer, "2023-02-01T10:00:00Z").await;
+
+    // When
+    let newest_first = app.get_issues_html("").await;
+    let oldest_first = app.get_issues_html("?order=asc").await;
+
+    // Then
+    assert!(newest_first.find("Newer issue").unwrap() < newest_first.find("Older issue").unwrap());
+    assert!(oldest_first.find("Older issue").unwrap() < oldest_first.find("Newer issue").unwrap());
+    assert!(newest_first.contains(&format!("/admin/newsletters/issues/{}", older)));
+}
+
+#[tokio::test]
+async fn issues_are_paginated() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..21 {
+        publish_newsletter(&app, &format!("Issue #{:02}", i)).await;
+    }
+
+    // When
+    let first_page = app.get_issues_html("?order=asc").await;
+    let second_page = app.get_issues_html("?page=2&order=asc").await;
+
+    // Then
+    assert!(first_page.contains("21 newsletter issues."));
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page.contains(r#"href="/admin/newsletters/issues?page=2&order=asc">Next</a>"#));
+    assert_eq!(first_page.matches("/clone\"").count(), 20);
+    assert!(second_page.contains("Page 2 of 2"));
+    assert_eq!(second_page.matches("/clone\"").count(), 1);
+}
+
+#[tokio::test]
+async fn out_of_range_pages_are_empty() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app, "Only issue").await;
+
+    // When
+    let html_page = app.get_issues_html(&format!("?page={}", i64::MAX)).await;
+
+    // Then
+    assert!(html_page.contains("1 newsletter issues."));
+    assert!(!html_page.contains("Only issue"));
+}
+
+#[tokio::test]
+async fn issues_can_be_cloned_into_a_draft() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app, "Original issue").await;
+
+    // When
+    let response = app.post_issue_action(issue_id, "clone").await;
+
+    // Then
+    let draft_url = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert!(draft_url.starts_with("/admin/newsletters/drafts/"));
+    let html_page = app.get_draft_html(&draft_url).await;
+    assert!(html_page.contains("The newsletter issue has been cloned into a new draft."));
+    assert!(html_page.contains(r#"value="Original issue""#));
+    assert!(html_page.contains("Newsletter body</textarea>"));
+}
+
+#[tokio::test]
+async fn issues_can_be_deleted_once_delivered() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&app, "Doomed issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    // When
+    let response = app.post_issue_action(issue_id, "delete").await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters/issues");
+    let html_page = app.get_issues_html("").await;
+    assert!(html_page
+        .contains("<p><i>The newsletter issue and its delivery log have been deleted.</i></p>"));
+    assert!(!html_page.contains("Doomed issue"));
+}
+
+#[tokio::test]
+async fn issues_with_pending_deliveries_cannot_be_deleted() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app, "Busy issue").await;
+
+    // When
+    let response = app.post_issue_action(issue_id, "delete").await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters/issues");
+    let html_page = app.get_issues_html("").await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue can't be deleted \
+        while 1 deliveries are still pending.</i></p>"
+    ));
+    assert!(html_page.contains("Busy issue"));
+}
+
+#[tokio::test]
+async fn issues_with_failed_deliveries_cannot_be_deleted() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let max_attempts = app.issue_delivery.max_retries as u64 + 1;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(max_attempts)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&app, "Failed issue").await;
+    for _ in 0..max_attempts {
+        app.skip_retry_backoff().await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // When
+    let response = app.post_issue_action(issue_id, "delete").await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters/issues");
+    let html_page = app.get_issues_html("").await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue can't be deleted while 1 failed deliveries \
+        are listed on the failures page.</i></p>"
+    ));
+    assert!(html_page.contains("Failed issue"));
+    let n_failures = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM issue_delivery_failures"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_failures, 1);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_past_issues() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/admin/newsletters/issues", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,360 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Asks for a reset link for the test user and returns the token it contains.
+async fn request_reset_token(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+        .await;
+    let email_request = app.wait_for_emails(1).await.pop().unwrap();
+    let links = app.get_password_reset_links(&email_request);
+    assert_eq!(links.html, links.plain_text);
+    links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn reset_body(token: &str, new_password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    })
+}
+
+async fn password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+}
+
+#[tokio::test]
+async fn the_login_form_links_to_the_password_reset_form() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let html_page = app.get_login_html().await;
+
+    // Then
+    assert!(html_page.contains(r#"<a href="/password-reset">Forgot your password?</a>"#));
+}
+
+#[tokio::test]
+async fn a_reset_link_is_emailed_to_known_addresses() {
+    // Given
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    let email_request = &app.wait_for_emails(1).await[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], app.test_user.email);
+    let links = app.get_password_reset_links(email_request);
+    let html_page = app
+        .api_client
+        .get(links.html)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"<form action="/password-reset/confirm" method="post">"#));
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_but_no_email() {
+    // Given
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": "nobody@example.com" }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(
+        "<p><i>If an account uses this email address, \
+        we have sent it a link to reset its password.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn a_failing_email_provider_gets_the_same_answer() {
+    // Given
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    app.wait_for_emails(1).await;
+}
+
+#[tokio::test]
+async fn reset_requests_for_an_address_are_throttled() {
+    // Given
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+    for _ in 0..5 {
+        app.post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+            .await;
+    }
+    app.wait_for_emails(5).await;
+
+    // When
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/password-reset");
+    let html_page = app.get_password_reset_html().await;
+    assert!(html_page
+        .contains("<p><i>Too many password reset requests. Please try again later.</i></p>"));
+}
+
+#[tokio::test]
+async fn reset_requests_from_an_ip_are_throttled_across_addresses() {
+    // Given
+    let app = spawn_app_with(|c| c.application.login_throttling.max_failures_per_ip = 3).await;
+    for _ in 0..3 {
+        app.post_password_reset_request(&serde_json::json!({
+            "email": format!("{}@example.com", uuid::Uuid::new_v4())
+        }))
+        .await;
+    }
+
+    // When
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/password-reset");
+}
+
+#[tokio::test]
+async fn disabled_users_cannot_reset_their_password() {
+    // Given
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET disabled = true WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": &app.test_user.email }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_new_password_replaces_the_old_one() {
+    // Given
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+
+    // When
+    let response = app
+        .post_password_reset(&reset_body(&token, &new_password))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your password has been reset. You can now log in.</i></p>"));
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_password_is_left_unchanged_if_the_reset_fails() {
+    // Given
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+    let old_password_hash = password_hash(&app).await;
+    // Sabotage the database so that revoking the user's sessions fails
+    sqlx::query!("ALTER TABLE user_sessions DROP COLUMN user_id;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = app
+        .post_password_reset(&reset_body(&token, "a-new-password"))
+        .await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 500);
+    assert_eq!(old_password_hash, password_hash(&app).await);
+}
+
+#[tokio::test]
+async fn reset_links_can_only_be_used_once() {
+    // Given
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+    app.post_password_reset(&reset_body(&token, "first-new-password"))
+        .await;
+
+    // When
+    let response = app
+        .post_password_reset(&reset_body(&token, "second-new-password"))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/password-reset");
+    let html_page = app.get_password_reset_html().await;
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "first-new-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = app
+        .post_password_reset(&reset_body(&token, "a-new-password"))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/password-reset");
+}
+
+#[tokio::test]
+async fn new_passwords_must_match() {
+    // Given
+    let app = spawn_app().await;
+    let token = request_reset_token(&app).await;
+
+    // When
+    let response = app
+        .post_password_reset(&serde_json::json!({
+            "token": &token,
+            "new_password": "a-new-password",
+            "new_password_check": "another-new-password",
+        }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(
+        &response,
+        &format!("/password-reset/confirm?token={}", token),
+    );
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_the_user_out_everywhere() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    let token = request_reset_token(&app).await;
+
+    // When
+    app.post_password_reset(&reset_body(&token, "a-new-password"))
+        .await;
+
+    // Then
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "a-new-password",
+    }))
+    .await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
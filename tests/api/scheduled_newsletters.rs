@@ -0,0 +1,236 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subsriber, spawn_app, TestApp};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn scheduled_newsletter_request_body(scheduled_for: &str, timezone: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "scheduled_for": scheduled_for,
+        "timezone": timezone,
+    })
+}
+
+fn tomorrow() -> String {
+    (Utc::now() + Duration::days(1))
+        .format("%Y-%m-%dT%H:%M")
+        .to_string()
+}
+
+async fn get_scheduled_issue(app: &TestApp) -> (Uuid, String, Option<DateTime<Utc>>) {
+    let r =
+        sqlx::query!("SELECT newsletter_issue_id, status, scheduled_for FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    (r.newsletter_issue_id, r.status, r.scheduled_for)
+}
+
+async fn make_scheduled_issues_due(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute' \
+        WHERE status = 'scheduled'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_not_sent_before_their_send_time() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_publish_newsletters(&scheduled_newsletter_request_body(&tomorrow(), "UTC"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Then
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled"));
+    app.publish_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+    let (_, status, _) = get_scheduled_issue(&app).await;
+    assert_eq!(status, "scheduled");
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_sent_once_due() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletters(&scheduled_newsletter_request_body(&tomorrow(), "UTC"))
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    make_scheduled_issues_due(&app).await;
+    app.publish_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Then
+    let (_, status, _) = get_scheduled_issue(&app).await;
+    assert_eq!(status, "published");
+}
+
+#[tokio::test]
+async fn scheduled_time_is_interpreted_in_the_given_timezone() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let next_year = Utc::now().format("%Y").to_string().parse::<i32>().unwrap() + 1;
+
+    // When
+    // Paris is on UTC+2 in July
+    app.post_publish_newsletters(&scheduled_newsletter_request_body(
+        &format!("{}-07-01T09:30", next_year),
+        "Europe/Paris",
+    ))
+    .await;
+
+    // Then
+    let (_, _, scheduled_for) = get_scheduled_issue(&app).await;
+    assert_eq!(
+        scheduled_for.unwrap().format("%Y-%m-%dT%H:%M").to_string(),
+        format!("{}-07-01T07:30", next_year)
+    );
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains(&format!("{}-07-01 09:30 (Europe/Paris)", next_year)));
+}
+
+#[tokio::test]
+async fn cancelled_scheduled_newsletters_are_never_sent() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletters(&scheduled_newsletter_request_body(&tomorrow(), "UTC"))
+        .await;
+    let (newsletter_issue_id, _, _) = get_scheduled_issue(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app
+        .post_cancel_scheduled_issue(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+
+    // Then
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    make_scheduled_issues_due(&app).await;
+    app.publish_due_scheduled_issues().await;
+    app.dispatch_all_pending_emails().await;
+    let (_, status, _) = get_scheduled_issue(&app).await;
+    assert_eq!(status, "cancelled");
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletters(&scheduled_newsletter_request_body(&tomorrow(), "UTC"))
+        .await;
+    let (newsletter_issue_id, _, _) = get_scheduled_issue(&app).await;
+    let next_year = Utc::now().format("%Y").to_string().parse::<i32>().unwrap() + 1;
+
+    // When
+    let response = app
+        .post_reschedule_issue(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "scheduled_for": format!("{}-01-15T08:00", next_year),
+            "timezone": "America/New_York",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+
+    // Then
+    let (_, status, scheduled_for) = get_scheduled_issue(&app).await;
+    assert_eq!(status, "scheduled");
+    assert_eq!(
+        scheduled_for.unwrap().format("%Y-%m-%dT%H:%M").to_string(),
+        format!("{}-01-15T13:00", next_year)
+    );
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been rescheduled.</i></p>"));
+}
+
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (
+            scheduled_newsletter_request_body(&tomorrow(), "Mars/Olympus_Mons"),
+            "an unknown timezone",
+        ),
+        (
+            scheduled_newsletter_request_body("next tuesday", "UTC"),
+            "a malformed send time",
+        ),
+        (
+            scheduled_newsletter_request_body("2000-01-01T10:00", "UTC"),
+            "a send time in the past",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // When
+        let response = app.post_publish_newsletters(&body).await;
+
+        // Then
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_scheduled_newsletters() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app
+        .api_client
+        .get(format!("{}/admin/newsletters/scheduled", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,177 @@
+use crate::helpers::{assert_is_redirect_to, random_ip, spawn_app, test_client, TestApp, TestUser};
+use uuid::Uuid;
+
+/// Logs `user` in from another device, with its own IP and cookies.
+async fn log_in_elsewhere(app: &TestApp, user: &TestUser, ip: &str) -> reqwest::Client {
+    let client = test_client(ip);
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "Other browser")
+        .form(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn session_ids(app: &TestApp, user_id: Uuid) -> Vec<Uuid> {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.session_id)
+    .collect()
+}
+
+#[tokio::test]
+async fn the_dashboard_lists_the_active_sessions() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_ip = random_ip();
+    log_in_elsewhere(&app, &app.test_user, &other_ip).await;
+
+    // When
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Then
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains(&other_ip));
+    assert!(html_page.contains("Other browser"));
+    let other_session_id = session_ids(&app, app.test_user.user_id).await[1];
+    assert!(html_page.contains(&format!(r#"value="{}""#, other_session_id)));
+}
+
+#[tokio::test]
+async fn the_sessions_of_other_users_are_not_listed() {
+    // Given
+    let app = spawn_app().await;
+    let other_user = TestUser::generate_with_role("editor");
+    other_user.store(&app.db_pool).await;
+    let other_ip = random_ip();
+    log_in_elsewhere(&app, &other_user, &other_ip).await;
+    app.test_user.login(&app).await;
+
+    // When
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Then
+    assert!(!html_page.contains(&other_ip));
+}
+
+#[tokio::test]
+async fn revoking_a_session_logs_it_out() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_client = log_in_elsewhere(&app, &app.test_user, &random_ip()).await;
+    let other_session_id = session_ids(&app, app.test_user.user_id).await[1];
+
+    // When
+    let response = app.post_revoke_session(other_session_id).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("<p><i>The session has been logged out.</i></p>"));
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn users_cannot_revoke_the_sessions_of_other_users() {
+    // Given
+    let app = spawn_app().await;
+    let other_user = TestUser::generate_with_role("editor");
+    other_user.store(&app.db_pool).await;
+    let other_client = log_in_elsewhere(&app, &other_user, &random_ip()).await;
+    let other_session_id = session_ids(&app, other_user.user_id).await[0];
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app.post_revoke_session(other_session_id).await;
+
+    // Then
+    assert_eq!(response.status().as_u16(), 404);
+    let response = get_dashboard(&app, &other_client).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn revoking_all_other_sessions_keeps_the_current_one() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first_client = log_in_elsewhere(&app, &app.test_user, &random_ip()).await;
+    let second_client = log_in_elsewhere(&app, &app.test_user, &random_ip()).await;
+
+    // When
+    let response = app.post_revoke_other_sessions().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("<p><i>All your other sessions have been logged out.</i></p>"));
+    for client in [first_client, second_client] {
+        let response = get_dashboard(&app, &client).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+    assert_eq!(session_ids(&app, app.test_user.user_id).await.len(), 1);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_the_other_sessions() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_client = log_in_elsewhere(&app, &app.test_user, &random_ip()).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // When
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    // Then
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn viewers_can_revoke_their_other_sessions() {
+    // Given
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+    let other_client = log_in_elsewhere(&app, &viewer, &random_ip()).await;
+
+    // When
+    let response = app.post_revoke_other_sessions().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+}
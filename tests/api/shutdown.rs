@@ -0,0 +1,91 @@
+use crate::helpers::{create_confirmed_subsriber, spawn_app, spawn_app_with, TestApp};
+use claims::assert_ok;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn n_email_requests(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+#[tokio::test]
+async fn the_worker_completes_its_delivery_in_flight_before_stopping() {
+    // Given
+    let app = spawn_app_with(|c| c.issue_delivery.n_workers = 1).await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let worker = app.spawn_worker();
+    let n_requests_before = n_email_requests(&app).await;
+    app.post_publish_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    while n_email_requests(&app).await == n_requests_before {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // When
+    app.shutdown_trigger.trigger();
+
+    // Then
+    let outcome = tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop in time.");
+    assert_ok!(outcome.unwrap());
+    let n_queued = sqlx::query!(r#"SELECT count(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(0, n_queued);
+}
+
+#[tokio::test]
+async fn an_idle_worker_stops_right_away() {
+    // Given
+    let app = spawn_app().await;
+    let worker = app.spawn_worker();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // When
+    app.shutdown_trigger.trigger();
+
+    // Then
+    let outcome = tokio::time::timeout(Duration::from_secs(1), worker)
+        .await
+        .expect("The worker did not stop in time.");
+    assert_ok!(outcome.unwrap());
+}
+
+#[tokio::test]
+async fn the_api_stops_accepting_connections() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    app.shutdown_trigger.trigger();
+
+    // Then
+    let health_check = || async {
+        reqwest::Client::new()
+            .get(format!("{}/health-check", &app.address))
+            .send()
+            .await
+    };
+    for _ in 0..50 {
+        if health_check().await.is_err() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The API still accepts connections.");
+}
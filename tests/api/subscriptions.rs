@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -23,6 +23,34 @@ async fn subscribe_should_return_200_when_form_is_valid() {
     assert_eq!(expected_status, response.status());
 }
 
+#[tokio::test]
+async fn confirmation_emails_are_not_held_back_by_the_delivery_rate_limit() {
+    // Given
+    let app = spawn_app_with(|c| {
+        c.email_client.rate_limit.domains = [("gmail.com".to_string(), 0.01)].into();
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula%40gmail.com".into())
+        .await;
+    let second = app
+        .post_subscriptions("name=victor&email=victor%40gmail.com".into())
+        .await;
+
+    // Then
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+}
+
 #[tokio::test]
 async fn subscribe_should_persist_subscriber_when_form_is_valid() {
     // Given
@@ -163,3 +191,128 @@ async fn subscribe_should_fail_when_fatal_database_error() {
     // Then
     assert_eq!(expected_status, response.status());
 }
+
+#[tokio::test]
+async fn subscribe_twice_while_pending_resends_a_fresh_confirmation_email() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula%40gmail.com";
+    let expected_status = 200;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let first_response = app.post_subscriptions(body.into()).await;
+    let second_response = app.post_subscriptions(body.into()).await;
+
+    // Then
+    assert_eq!(expected_status, first_response.status());
+    assert_eq!(expected_status, second_response.status());
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+
+    let first_confirmation = reqwest::get(first_links.html).await.unwrap();
+    assert_eq!(401, first_confirmation.status());
+    let second_confirmation = reqwest::get(second_links.html).await.unwrap();
+    assert_eq!(200, second_confirmation.status());
+}
+
+#[tokio::test]
+async fn concurrent_first_time_subscriptions_with_the_same_email_both_succeed() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let (first_response, second_response) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    // Then
+    assert_eq!(200, first_response.status());
+    assert_eq!(200, second_response.status());
+    let saved = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(1, saved.count);
+}
+
+#[tokio::test]
+async fn subscribe_with_an_already_confirmed_email_returns_200_without_sending_an_email() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula%40gmail.com";
+
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    drop(mock_guard);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Then
+    assert_eq!(200, response.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!("confirmed", saved.status);
+}
+
+#[tokio::test]
+async fn subscribe_should_store_a_token_that_expires() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    app.post_subscriptions(body.into()).await;
+
+    // Then
+    let saved = sqlx::query!("SELECT created_at, expires_at FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+    assert!(saved.expires_at > saved.created_at);
+}
@@ -75,3 +75,37 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(expected_name, saved.name);
     assert_eq!(expected_status, saved.status);
 }
+
+#[tokio::test]
+async fn should_return_410_when_the_confirmation_link_has_expired() {
+    // Given
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula%40gmail.com";
+    let expected_status = 410;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // When
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Then
+    assert_eq!(expected_status, response.status());
+    assert!(response.text().await.unwrap().contains("has expired"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!("pending_confirmation", saved.status);
+}
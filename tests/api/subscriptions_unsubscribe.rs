@@ -0,0 +1,203 @@
+use crate::helpers::{
+    create_confirmed_subsriber, create_unconfirmed_subscriber, spawn_app, TestApp, UnsubscribeLinks,
+};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as html</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletters(&newsletter_request_body).await;
+}
+
+async fn receive_newsletter_unsubscribe_links(app: &TestApp) -> UnsubscribeLinks {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(app).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_unsubscribe_links(email_request)
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[tokio::test]
+async fn newsletter_emails_include_one_click_unsubscribe_headers() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // When
+    let links = receive_newsletter_unsubscribe_links(&app).await;
+
+    // Then
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let headers = body["Headers"].as_array().unwrap();
+    assert!(headers.contains(&serde_json::json!({
+        "Name": "List-Unsubscribe-Post",
+        "Value": "List-Unsubscribe=One-Click",
+    })));
+    assert_eq!(links.html, links.plain_text);
+    assert_eq!(links.html, links.header);
+}
+
+#[tokio::test]
+async fn clicking_on_the_unsubscribe_link_unsubscribes_a_subscriber() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let links = receive_newsletter_unsubscribe_links(&app).await;
+
+    // When
+    let response = reqwest::get(links.html).await.unwrap();
+
+    // Then
+    assert_eq!(200, response.status());
+    assert_eq!("unsubscribed", subscriber_status(&app).await);
+}
+
+#[tokio::test]
+async fn a_one_click_post_unsubscribes_a_subscriber() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let links = receive_newsletter_unsubscribe_links(&app).await;
+
+    // When
+    let response = reqwest::Client::new()
+        .post(links.header)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(200, response.status());
+    assert_eq!("unsubscribed", subscriber_status(&app).await);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let links = receive_newsletter_unsubscribe_links(&app).await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // When
+    publish_newsletter(&app).await;
+
+    // Then
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_with_a_tampered_token_is_rejected_with_401() {
+    // Given
+    let app = spawn_app().await;
+    create_confirmed_subsriber(&app).await;
+    app.test_user.login(&app).await;
+    let links = receive_newsletter_unsubscribe_links(&app).await;
+    let mut tampered_link = links.html.clone();
+    let token = links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    tampered_link
+        .query_pairs_mut()
+        .clear()
+        .append_pair("subscriber_id", &uuid::Uuid::new_v4().to_string())
+        .append_pair("token", &token);
+
+    // When
+    let response = reqwest::get(tampered_link).await.unwrap();
+
+    // Then
+    assert_eq!(401, response.status());
+    assert_eq!("confirmed", subscriber_status(&app).await);
+}
+
+#[tokio::test]
+async fn unsubscribing_without_parameters_is_rejected_with_400() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(400, response.status());
+}
+
+#[tokio::test]
+async fn an_old_confirmation_link_does_not_resubscribe_an_unsubscribed_subscriber() {
+    // Given
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.test_user.login(&app).await;
+    let links = receive_newsletter_unsubscribe_links(&app).await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // When
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Then
+    assert_eq!(200, response.status());
+    assert_eq!("unsubscribed", subscriber_status(&app).await);
+}
@@ -0,0 +1,261 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use chrono::Utc;
+use zero2prod::authentication::TotpSecret;
+
+fn login_body(user: &TestUser) -> serde_json::Value {
+    serde_json::json!({
+        "username": &user.username,
+        "password": &user.password,
+    })
+}
+
+/// A code the server hasn't seen yet: the current one was used to confirm the setup.
+fn next_code(secret: &TotpSecret) -> String {
+    secret.code_at(Utc::now().timestamp() + 30)
+}
+
+async fn enable_two_factor_and_log_out(app: &TestApp) -> (TotpSecret, Vec<String>) {
+    app.test_user.login(app).await;
+    let enrollment = app.enable_two_factor(app.test_user.user_id).await;
+    app.post_logout().await;
+    enrollment
+}
+
+#[tokio::test]
+async fn setting_up_two_factor_shows_a_qr_code_and_the_secret() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app.post_two_factor("setup", &serde_json::json!({})).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_two_factor_html().await;
+    let secret = sqlx::query!(
+        r#"SELECT totp_secret as "totp_secret!" FROM users WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .totp_secret;
+    assert!(html_page.contains("<svg"));
+    assert!(html_page.contains(&format!("<code>{}</code>", secret)));
+}
+
+#[tokio::test]
+async fn two_factor_is_only_enabled_with_a_valid_code() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_two_factor("setup", &serde_json::json!({})).await;
+
+    // When
+    let response = app
+        .post_two_factor("confirm", &serde_json::json!({ "code": "000000" }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The code is not valid.</i></p>"));
+    let (_, recovery_codes) = app.enable_two_factor(app.test_user.user_id).await;
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication is enabled."));
+}
+
+#[tokio::test]
+async fn the_password_alone_is_not_enough_once_two_factor_is_enabled() {
+    // Given
+    let app = spawn_app().await;
+    enable_two_factor_and_log_out(&app).await;
+
+    // When
+    let response = app.post_login(&login_body(&app.test_user)).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_valid_code_completes_the_login() {
+    // Given
+    let app = spawn_app().await;
+    let (secret, _) = enable_two_factor_and_log_out(&app).await;
+    app.post_login(&login_body(&app.test_user)).await;
+
+    // When
+    let response = app.post_login_two_factor(&next_code(&secret)).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn an_invalid_code_is_rejected() {
+    // Given
+    let app = spawn_app().await;
+    enable_two_factor_and_log_out(&app).await;
+    app.post_login(&login_body(&app.test_user)).await;
+
+    // When
+    let response = app.post_login_two_factor("123456").await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let html_page = app
+        .api_client
+        .get(format!("{}/login/two-factor", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The code is not valid.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    // Given
+    let app = spawn_app().await;
+    let (secret, _) = enable_two_factor_and_log_out(&app).await;
+    let code = next_code(&secret);
+    app.post_login(&login_body(&app.test_user)).await;
+    app.post_login_two_factor(&code).await;
+    app.post_logout().await;
+    app.post_login(&login_body(&app.test_user)).await;
+
+    // When
+    let response = app.post_login_two_factor(&code).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_work_once() {
+    // Given
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enable_two_factor_and_log_out(&app).await;
+    app.post_login(&login_body(&app.test_user)).await;
+
+    // When
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+    app.post_login(&login_body(&app.test_user)).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_step_requires_a_valid_password_first() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let form_response = app
+        .api_client
+        .get(format!("{}/login/two-factor", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let response = app.post_login_two_factor("123456").await;
+
+    // Then
+    assert_is_redirect_to(&form_response, "/login");
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn disabling_two_factor_requires_a_valid_code() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = app.enable_two_factor(app.test_user.user_id).await;
+
+    // When
+    app.post_two_factor("disable", &serde_json::json!({ "code": "000000" }))
+        .await;
+    assert!(app
+        .get_two_factor_html()
+        .await
+        .contains("Two-factor authentication is enabled."));
+    app.post_two_factor(
+        "disable",
+        &serde_json::json!({ "code": next_code(&secret) }),
+    )
+    .await;
+
+    // Then
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("<p><i>Two-factor authentication has been disabled.</i></p>"));
+    app.post_logout().await;
+    let response = app.post_login(&login_body(&app.test_user)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn owners_can_require_two_factor_for_everyone() {
+    // Given
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/users/two-factor", &app.address))
+        .form(&serde_json::json!({ "required": true }))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/users");
+    app.post_logout().await;
+    editor.login(&app).await;
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let response = app
+        .post_two_factor("disable", &serde_json::json!({ "code": "1" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    app.enable_two_factor(editor.user_id).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn only_owners_can_require_two_factor() {
+    // Given
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // When
+    let response = app
+        .api_client
+        .post(format!("{}/admin/users/two-factor", &app.address))
+        .form(&serde_json::json!({ "required": true }))
+        .send()
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,310 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestUser};
+use uuid::Uuid;
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    // Given
+    let app = spawn_app().await;
+
+    // When
+    let response = app.get_users().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_can_add_users_who_can_then_log_in() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-long-password",
+            "password_check": "a-long-password",
+            "role": "editor",
+        }))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>ursula has been added as editor.</i></p>"));
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-long-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are logged in as editor."));
+    assert!(!html_page.contains("Manage users"));
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    app.post_create_user(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "a-long-password",
+        "password_check": "a-long-password",
+        "role": "viewer",
+    }))
+    .await;
+
+    // Then
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>That username or email address is already taken.</i></p>"));
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    for role in ["editor", "viewer"] {
+        // Given
+        let app = spawn_app().await;
+        let user = TestUser::generate_with_role(role);
+        user.store(&app.db_pool).await;
+        user.login(&app).await;
+
+        // When
+        let page_response = app.get_users().await;
+        let create_response = app
+            .post_create_user(&serde_json::json!({
+                "username": "intruder",
+                "password": "a-long-password",
+                "password_check": "a-long-password",
+                "role": "owner",
+            }))
+            .await;
+        let disable_response = app
+            .post_user_action(app.test_user.user_id, "disable", &serde_json::json!({}))
+            .await;
+
+        // Then
+        assert_eq!(page_response.status().as_u16(), 403);
+        assert_eq!(create_response.status().as_u16(), 403);
+        assert_eq!(disable_response.status().as_u16(), 403);
+        let n_users = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM users WHERE NOT disabled"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+        // The seeded admin, the test owner and the user we logged in with.
+        assert_eq!(n_users, 3);
+    }
+}
+
+#[tokio::test]
+async fn viewers_can_read_dashboards_but_not_publish() {
+    // Given
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // When
+    let dashboard_response = app.get_admin_dashboard().await;
+    let issues_html = app.get_issues_html("").await;
+    let publish_response = app
+        .post_publish_newsletters(&newsletter_request_body())
+        .await;
+
+    // Then
+    assert_eq!(dashboard_response.status().as_u16(), 200);
+    assert!(issues_html.contains("0 newsletter issues."));
+    assert_eq!(publish_response.status().as_u16(), 403);
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn viewers_can_still_log_out() {
+    // Given
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // When
+    let response = app.post_logout().await;
+
+    // Then
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn editors_can_publish() {
+    // Given
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // When
+    let response = app
+        .post_publish_newsletters(&newsletter_request_body())
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn owners_can_change_the_role_of_other_users() {
+    // Given
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app
+        .post_user_action(
+            viewer.user_id,
+            "role",
+            &serde_json::json!({ "role": "editor" }),
+        )
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/users");
+    let role = sqlx::query!("SELECT role FROM users WHERE user_id = $1", viewer.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "editor");
+}
+
+#[tokio::test]
+async fn disabled_users_cannot_log_in() {
+    // Given
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // When
+    let response = app
+        .post_user_action(editor.user_id, "disable", &serde_json::json!({}))
+        .await;
+
+    // Then
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("<p><i>The user has been disabled.</i></p>"));
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &editor.username,
+            "password": &editor.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn disabling_a_user_ends_their_sessions() {
+    // Given
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+
+    // When
+    sqlx::query!(
+        "UPDATE users SET disabled = true WHERE user_id = $1",
+        editor.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Then
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_cannot_disable_themselves() {
+    // Given
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // When
+    app.post_user_action(app.test_user.user_id, "disable", &serde_json::json!({}))
+        .await;
+
+    // Then
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>You can't disable your own account.</i></p>"));
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn owners_can_change_the_email_address_of_users() {
+    // Given
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // When
+    let invalid_response = app
+        .post_user_action(
+            editor.user_id,
+            "email",
+            &serde_json::json!({ "email": "not-an-email" }),
+        )
+        .await;
+    let taken_response = app
+        .post_user_action(
+            editor.user_id,
+            "email",
+            &serde_json::json!({ "email": &app.test_user.email }),
+        )
+        .await;
+    let response = app
+        .post_user_action(
+            editor.user_id,
+            "email",
+            &serde_json::json!({ "email": "editor@example.com" }),
+        )
+        .await;
+
+    // Then
+    assert_is_redirect_to(&invalid_response, "/admin/users");
+    assert_is_redirect_to(&taken_response, "/admin/users");
+    assert_is_redirect_to(&response, "/admin/users");
+    let email = sqlx::query!("SELECT email FROM users WHERE user_id = $1", editor.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email.as_deref(), Some("editor@example.com"));
+}